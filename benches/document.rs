@@ -0,0 +1,95 @@
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion};
+use revise::{Document, Position, Query, Rope, Row};
+use std::fs;
+use std::hint::black_box;
+
+const LINES: usize = 200_000;
+
+fn line(index: usize) -> String {
+    format!("{index:>8} | let value = compute(\"entry\", {index}); // generated line")
+}
+
+fn rows() -> Vec<Row> {
+    (0..LINES).map(|i| Row::from(&line(i)[..])).collect()
+}
+
+fn large_document() -> Document {
+    let path = std::env::temp_dir().join("revise-bench.rs");
+
+    if !path.exists() {
+        let contents = (0..LINES).map(line).collect::<Vec<_>>().join("\n");
+
+        fs::write(&path, contents).expect("failed to write benchmark file");
+    }
+
+    Document::open(path.to_str().expect("non utf-8 temp dir")).expect("failed to open file")
+}
+
+fn bench_line_storage(c: &mut Criterion) {
+    let mut group = c.benchmark_group("line storage");
+
+    group.bench_function("vec insert+remove at head", |b| {
+        b.iter_batched_ref(
+            rows,
+            |rows| {
+                rows.insert(1, Row::from("new line"));
+                black_box(rows.remove(1));
+            },
+            BatchSize::LargeInput,
+        );
+    });
+    group.bench_function("rope insert+remove at head", |b| {
+        b.iter_batched_ref(
+            || Rope::from(rows()),
+            |rope| {
+                rope.insert(1, Row::from("new line"));
+                black_box(rope.remove(1));
+            },
+            BatchSize::LargeInput,
+        );
+    });
+    group.bench_function("rope line lookup", |b| {
+        let rope = Rope::from(rows());
+
+        b.iter(|| black_box(rope.get(black_box(LINES / 2))));
+    });
+    group.finish();
+}
+
+fn bench_document_edits(c: &mut Criterion) {
+    let mut group = c.benchmark_group("document edits");
+    let mut document = large_document();
+
+    group.bench_function("type and split line near top", |b| {
+        b.iter(|| {
+            let at = Position { x: 4, y: 10 };
+
+            document.insert(&at, 'x').unwrap();
+            document.insert(&at, '\n').unwrap();
+            document.delete(&Position { x: 4, y: 10 }).unwrap();
+            document.delete(&at).unwrap();
+        });
+    });
+    group.bench_function("highlight first screen", |b| {
+        b.iter(|| {
+            document.insert(&Position { x: 0, y: 0 }, 'x').unwrap();
+            document.highlight(None, Some(50)).unwrap();
+            document.delete(&Position { x: 0, y: 0 }).unwrap();
+        });
+    });
+    group.bench_function("find near end", |b| {
+        let at = Position { x: 0, y: 0 };
+        let pattern = Query {
+            text: "199999".to_owned(),
+            ..Query::default()
+        }
+        .compile()
+        .unwrap();
+
+        b.iter(|| black_box(document.find(&pattern, &at, revise::SearchDirection::Forward)));
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench_line_storage, bench_document_edits);
+criterion_main!(benches);
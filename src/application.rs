@@ -1,20 +1,55 @@
+use crate::buffer::Wrap;
+use crate::command_line::{self, Invocation, Setting};
+use crate::config::MAX_TAB_WIDTH;
+use crate::diff;
+use crate::emacs::{self, KillRing};
+use crate::goto::{self, Target};
+use crate::keymap::{self, Command, Keymap, Lookup};
+use crate::pane::{Layout, Pane, Rect, SplitDirection};
+use crate::prompt::{LineEditor, PromptHistory, PromptKind};
+use crate::query::{self, Pattern, Query};
+use crate::swap::Swap;
+use crate::terminal::{Input, Size};
+use crate::vi::{Mode, Outcome, Vi};
+use crate::Buffer;
+use crate::Config;
 use crate::Document;
+use crate::FileType;
+use crate::LineEnding;
 use crate::Row;
 use crate::Terminal;
 
 use cli_clipboard::{ClipboardContext, ClipboardProvider};
+use serde::Deserialize;
+use std::cmp::Ordering;
 use std::env::{self, Args};
 use std::error::Error as Err;
+use std::fs;
 use std::io::Error as IOError;
+use std::mem;
+use std::ops::Range;
+use std::path::Path;
 use std::time::{Duration, Instant};
-use termion::color;
 use termion::event::Key;
 use thiserror::Error;
 
 const VERSION: &str = env!("CARGO_PKG_VERSION");
-const STATUS_FG_COLOR: color::Rgb = color::Rgb(63, 63, 63);
-const STATUS_BG_COLOR: color::Rgb = color::Rgb(239, 239, 239);
-const QUIT_TIME: u8 = 1;
+const MIN_PANE_HEIGHT: u16 = 3;
+const MIN_PANE_WIDTH: u16 = 10;
+const IDLE_TIMEOUT: Duration = Duration::from_secs(1);
+const SWAP_INTERVAL: Duration = Duration::from_secs(4);
+const DISK_CHECK_INTERVAL: Duration = Duration::from_secs(2);
+/// How long since the last key before asking about a file changed on disk.
+const TYPING_PAUSE: Duration = Duration::from_secs(3);
+/// Command line flags, those ending with `=` taking a value.
+const FLAGS: [&str; 6] = [
+    "--backup",
+    "--config=",
+    "--line-numbers",
+    "--relative-line-numbers",
+    "--tab-width=",
+    "--vi",
+];
 
 #[derive(Clone, Copy, PartialEq)]
 pub enum SearchDirection {
@@ -22,7 +57,18 @@ pub enum SearchDirection {
     Backward,
 }
 
-#[derive(Default, Clone)]
+/// What the gutter left of the text shows.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum LineNumbers {
+    #[default]
+    Off,
+    Absolute,
+    /// Distance to the cursor line, which shows its own number.
+    Relative,
+}
+
+#[derive(Default, Clone, Debug, PartialEq)]
 pub struct Position {
     pub x: usize,
     pub y: usize,
@@ -47,58 +93,204 @@ pub struct Revise {
     terminal: Terminal,
     cursor_position: Position,
     offset: Position,
-    document: Document,
+    buffers: Vec<Buffer>,
+    current: usize,
+    panes: Vec<Pane>,
+    active: usize,
+    layout: Layout,
+    anchor: Option<Position>,
+    marking: bool,
+    /// The vi layer, when modal editing is on.
+    vi: Option<Vi>,
+    line_numbers: LineNumbers,
+    config: Config,
+    keymap: Keymap,
+    pending_keys: Vec<Key>,
+    /// The command run by the previous key press, for consecutive kills to
+    /// add to the same kill ring entry and yank-pop to follow a yank.
+    last_command: Option<Command>,
+    kill_ring: KillRing,
+    /// Where the last yank put its text.
+    yanked: Option<(Position, Position)>,
     status_message: StatusMessage,
     quit_times: u8,
-    highlighted_word: Option<String>,
+    /// Set by `quit`, so the key asking to quit does not reset `quit_times`.
+    quit_requested: bool,
+    highlighted_word: Option<Pattern>,
+    query: Query,
+    prompt_notice: String,
+    prompt_cursor: Option<usize>,
+    prompt_history: PromptHistory,
     clipboard: ClipboardContext,
+    swapped_at: Instant,
+    disk_checked_at: Instant,
+    /// When the last input came.
+    typed_at: Instant,
 }
 
 #[derive(Debug, Error)]
 #[error("Cannot copy content")]
 pub struct CopyError;
 
+#[derive(Debug, Error)]
+#[error("Nothing selected to cut")]
+pub struct CutError;
+
 impl Revise {
     pub fn new() -> Result<Self, Box<dyn Err>> {
-        let mut args: Args = env::args();
-        let mut initial_status =
-            String::from("HELP: Ctrl-F = find | Ctrl-S = save | Ctrl-Q = quit");
-        let document = if args.len() > 1 {
-            let filename = args.nth(1);
-
-            match filename {
-                Some(f) => {
-                    let doc = Document::open(f.as_str());
-
-                    if let Ok(content) = doc {
-                        content
+        let args: Args = env::args();
+        let (flags, files): (Vec<String>, Vec<String>) =
+            args.skip(1).partition(|arg| arg.starts_with("--"));
+        let mut initial_status = String::new();
+        let config_path = flags.iter().find_map(|flag| flag.strip_prefix("--config="));
+        let mut config = Config::load(config_path).unwrap_or_else(|err| {
+            initial_status = format!("ERR: {err}");
+            Config::default()
+        });
+        let (keymap, problems) = Keymap::new(config.keymap, &config.keys);
+
+        if !problems.is_empty() {
+            initial_status = format!("WARNING: key bindings: {}", problems.join("; "));
+        } else if initial_status.is_empty() {
+            initial_status = help(&keymap);
+        }
+
+        let unknown_flags: Vec<&str> = flags
+            .iter()
+            .map(String::as_str)
+            .filter(|flag| {
+                !FLAGS.iter().any(|known| {
+                    if known.ends_with('=') {
+                        flag.starts_with(known)
                     } else {
-                        initial_status = format!("ERR: Could not open file: {f}");
-                        Document::default()
+                        flag == known
                     }
-                }
-                None => Document::default(),
-            }
+                })
+            })
+            .collect();
+
+        if !unknown_flags.is_empty() {
+            initial_status = format!("ERR: unknown option: {}", unknown_flags.join(", "));
+        }
+
+        let backup = flags.iter().any(|flag| flag == "--backup");
+        let vi_mode = config.vi_mode || flags.iter().any(|flag| flag == "--vi");
+        let line_numbers = if flags.iter().any(|flag| flag == "--relative-line-numbers") {
+            LineNumbers::Relative
+        } else if flags.iter().any(|flag| flag == "--line-numbers") {
+            LineNumbers::Absolute
         } else {
-            Document::default()
+            config.line_numbers
         };
+
+        if let Some(width) = flags
+            .iter()
+            .find_map(|flag| flag.strip_prefix("--tab-width="))
+        {
+            match width.parse() {
+                Ok(width) if (1..=MAX_TAB_WIDTH).contains(&width) => config.tab_width = width,
+                _ => {
+                    initial_status = format!(
+                        "ERR: --tab-width must be between 1 and {MAX_TAB_WIDTH}, not `{width}`"
+                    );
+                }
+            }
+        }
+
+        let mut buffers = Vec::new();
+        // Set by a `+<line>` argument for the file after it.
+        let mut target = None;
+
+        for f in &files {
+            if let Some(line) = f.strip_prefix('+').and_then(Target::parse) {
+                target = Some(line);
+                continue;
+            }
+
+            let (f, location) = match goto::split_location(f) {
+                Some((filename, location)) if !Path::new(f).exists() => (filename, Some(location)),
+                _ => (f.as_str(), None),
+            };
+            // Only ever meant for this file, even if it has its own line or
+            // fails to open.
+            let target = location.or(target.take());
+
+            match Document::open(f) {
+                Ok(document) => {
+                    let mut buffer = Buffer::new(document);
+
+                    if let Some(target) = target {
+                        buffer.cursor_position =
+                            target.resolve(&buffer.document, &Position::default());
+                    }
+
+                    buffers.push(buffer);
+                }
+                Err(_) => initial_status = format!("ERR: Could not open file: {f}"),
+            }
+        }
+
+        if buffers.is_empty() {
+            buffers.push(Buffer::default());
+        }
+
+        for buffer in &mut buffers {
+            buffer.document.set_backup(backup);
+
+            if let Some(warning) = buffer.check_swap() {
+                initial_status = warning;
+            }
+        }
+
         let terminal = Terminal::new()?;
         let clipboard = ClipboardContext::new()?;
 
         Ok(Self {
             should_quit: false,
             terminal,
-            cursor_position: Position::default(),
+            cursor_position: buffers[0].cursor_position.clone(),
             offset: Position::default(),
-            document,
+            buffers,
+            current: 0,
+            panes: vec![Pane::default()],
+            active: 0,
+            layout: Layout::default(),
+            anchor: None,
+            marking: false,
+            vi: vi_mode.then(Vi::default),
+            line_numbers,
+            quit_times: config.quit_times,
+            quit_requested: false,
+            config,
+            keymap,
+            pending_keys: Vec::new(),
+            last_command: None,
+            kill_ring: KillRing::default(),
+            yanked: None,
             status_message: StatusMessage::from(initial_status),
-            quit_times: QUIT_TIME,
             highlighted_word: None,
+            query: Query::default(),
+            prompt_notice: String::new(),
+            prompt_cursor: None,
+            prompt_history: PromptHistory::load(),
             clipboard,
+            swapped_at: Instant::now(),
+            disk_checked_at: Instant::now(),
+            typed_at: Instant::now(),
         })
     }
 
     pub fn run(&mut self) -> Result<(), Box<dyn Err>> {
+        for index in 0..self.buffers.len() {
+            if let Some(swap) = self.buffers[index].pending_swap.take() {
+                self.switch_buffer(index);
+                self.offer_recovery(swap)?;
+            }
+        }
+
+        self.switch_buffer(0);
+        self.scroll();
+
         loop {
             if let Err(error) = self.refresh_screen() {
                 match self.clipboard.clear() {
@@ -108,6 +300,9 @@ impl Revise {
             }
 
             if self.should_quit {
+                for buffer in &mut self.buffers {
+                    buffer.remove_swap();
+                }
                 break;
             }
 
@@ -122,413 +317,2047 @@ impl Revise {
         Ok(())
     }
 
-    pub fn draw_row(&self, row: &Row) {
-        let start = self.offset.x;
-        let width = self.terminal.size().width as usize;
-        let end = start.saturating_add(width);
-        let row = row.render(start, end);
+    pub fn draw_row(
+        &self,
+        row: &Row,
+        columns: Range<usize>,
+        width: usize,
+        selection: &Range<usize>,
+    ) {
+        let visible = row
+            .width(self.config.tab_width)
+            .min(columns.end)
+            .saturating_sub(columns.start);
+        let row = row.render(
+            columns.start,
+            columns.end,
+            selection,
+            self.config.tab_width,
+            &self.config.colors,
+        );
 
-        println!("{row}\r");
+        print!("{row}{}", " ".repeat(width.saturating_sub(visible)));
     }
 
-    fn process_keypress(&mut self) -> Result<(), IOError> {
-        let pressed_key = Terminal::read_key()?;
+    /// Picks up the new terminal size and keeps the cursor in view.
+    fn resize(&mut self) -> Result<(), IOError> {
+        self.terminal.update_size()?;
+        self.scroll_panes();
+        Terminal::clear_screen();
 
-        match pressed_key {
-            Key::Ctrl('c') => match self.copy_content() {
-                Ok(_) => (),
-                Err(err) => self.status_message = StatusMessage::from(format!("{err}")),
-            },
-            Key::Ctrl('v') => match self.paste_content() {
-                Ok(v) => {
-                    for c in v.chars().rev() {
-                        match self.document.insert(&self.cursor_position, c) {
-                            Ok(_) => (),
-                            Err(err) => {
-                                self.status_message =
-                                    StatusMessage::from(format!("Failed to paste content: {err}"))
-                            }
-                        }
-                    }
-                }
-                Err(err) => {
-                    self.status_message =
-                        StatusMessage::from(format!("Failed to paste content: {err}"))
-                }
-            },
-            Key::Ctrl('q') => return self.quit(),
-            Key::Ctrl('s') => self.save(),
-            Key::Ctrl('f') => self.search(),
-            Key::Char(c) => match self.document.insert(&self.cursor_position, c) {
-                Ok(_) => self.move_cursor(Key::Right),
-                Err(err) => {
-                    self.status_message =
-                        StatusMessage::from(format!("Failed to paste content: {err}"))
-                }
-            },
-            Key::Delete => match self.document.delete(&self.cursor_position) {
-                Ok(_) => (),
-                Err(err) => {
-                    self.status_message =
-                        StatusMessage::from(format!("Failed to remove content: {err}"))
-                }
-            },
-            Key::Backspace => {
-                if self.cursor_position.x > 0 || self.cursor_position.y > 0 {
-                    self.move_cursor(Key::Left);
-
-                    match self.document.delete(&self.cursor_position) {
-                        Ok(_) => (),
-                        Err(err) => {
-                            self.status_message =
-                                StatusMessage::from(format!("Failed to remove character: {err}"))
-                        }
-                    }
+        Ok(())
+    }
+
+    /// Waits for a key, redrawing the screen if the terminal is resized
+    /// meanwhile.
+    fn read_key(&mut self) -> Result<Key, IOError> {
+        loop {
+            match self.terminal.read_input()? {
+                Input::Key(key) | Input::Shift(key) => return Ok(key),
+                Input::Paste(_) => (),
+                Input::Resize => {
+                    self.resize()?;
+                    self.refresh_screen()
+                        .map_err(|err| IOError::other(err.to_string()))?;
                 }
             }
-            Key::Up
-            | Key::Down
-            | Key::Left
-            | Key::Right
-            | Key::PageUp
-            | Key::PageDown
-            | Key::End
-            | Key::Home => self.move_cursor(pressed_key),
-            _ => (),
-        }
-
-        self.scroll();
-
-        if self.quit_times < QUIT_TIME {
-            self.quit_times = QUIT_TIME;
-            self.status_message = StatusMessage::from(String::new());
         }
-
-        Ok(())
     }
 
-    fn quit(&mut self) -> Result<(), IOError> {
-        if self.quit_times > 0 && self.document.is_changed() {
-            self.status_message = StatusMessage::from(format!(
-                "WARNING! File has unsaved changes. Press Ctrl-Q {} more time to quit.",
-                self.quit_times
-            ));
-            self.quit_times -= 1;
+    fn process_keypress(&mut self) -> Result<(), IOError> {
+        let Some(input) = self.terminal.read_input_timeout(IDLE_TIMEOUT)? else {
+            self.update_swap();
+
+            if self.disk_checked_at.elapsed() >= DISK_CHECK_INTERVAL && self.is_idle() {
+                self.check_disk();
+            }
 
             return Ok(());
-        }
-        self.should_quit = true;
+        };
 
-        Ok(())
-    }
+        self.typed_at = Instant::now();
 
-    fn refresh_screen(&mut self) -> Result<(), Box<dyn Err>> {
-        Terminal::cursor_hide();
-        Terminal::cursor_position(&Position::default());
+        let pressed_key = match input {
+            Input::Key(key) => key,
+            Input::Shift(key) => {
+                self.last_command = None;
+                self.select(key);
+                self.scroll();
 
-        if self.should_quit {
-            Terminal::clear_screen();
-        } else {
-            match self.document.highlight(
-                &self.highlighted_word,
-                Some(
-                    self.offset
-                        .y
-                        .saturating_add(self.terminal.size().height as usize),
-                ),
-            ) {
-                Ok(_) => {
-                    self.draw_rows();
-                    self.draw_status_bar();
-                    self.draw_message_bar();
-                    Terminal::cursor_position(&Position {
-                        x: self.cursor_position.x.saturating_sub(self.offset.x),
-                        y: self.cursor_position.y.saturating_sub(self.offset.y),
-                    });
-                }
-                Err(err) => return Err(err),
+                return Ok(());
             }
-        }
+            Input::Paste(text) => {
+                self.last_command = None;
+                self.paste(&text);
+                self.scroll();
 
-        Terminal::cursor_show();
+                return Ok(());
+            }
+            Input::Resize => return self.resize(),
+        };
 
-        match Terminal::flush() {
-            Ok(_) => Ok(()),
-            Err(err) => return Err(Box::new(err)),
+        if self.pending_keys.is_empty() && self.vi_key(pressed_key) {
+            self.last_command = None;
+            self.after_key();
+
+            return Ok(());
         }
-    }
 
-    fn draw_rows(&self) {
-        let height = self.terminal.size().height;
+        self.pending_keys.push(pressed_key);
 
-        for terminal_row in 0..height {
-            Terminal::clear_current_line();
+        match self.keymap.lookup(&self.pending_keys) {
+            Lookup::Prefix => {
+                let chord = keymap::describe(&self.pending_keys);
 
-            if let Some(row) = self
-                .document
-                .row(self.offset.y.saturating_add(terminal_row as usize))
-            {
-                self.draw_row(row);
-            } else if self.document.is_empty() && terminal_row == height / 3 {
-                self.draw_welcome_message();
-            } else {
-                println!("~\r");
+                self.status_message = StatusMessage::from(format!("{chord}-"));
+                return Ok(());
+            }
+            Lookup::Command(command) => {
+                if self.pending_keys.len() > 1 {
+                    self.status_message = StatusMessage::from(String::new());
+                }
+
+                self.pending_keys.clear();
+                self.run_command(command);
+                self.last_command = Some(command);
+            }
+            Lookup::Unbound => {
+                let keys = mem::take(&mut self.pending_keys);
+                let cancel = Lookup::Command(Command::ClearSelection);
+
+                self.last_command = None;
+
+                match keys[..] {
+                    [Key::Char(c)] => self.insert_char(c),
+                    [_] => (),
+                    // The key that clears the selection also cancels a chord.
+                    [.., last] if self.keymap.lookup(&[last]) == cancel => {
+                        self.status_message = StatusMessage::from("Quit".to_owned());
+                    }
+                    _ => {
+                        self.status_message = StatusMessage::from(format!(
+                            "{} is not bound",
+                            keymap::describe(&keys)
+                        ));
+                    }
+                }
             }
         }
+
+        self.after_key();
+
+        Ok(())
     }
 
-    fn draw_welcome_message(&self) {
-        let mut welcome_message = format!("Revise | v{VERSION}");
-        let width = self.terminal.size().width as usize;
-        let len = welcome_message.len();
-        let padding = width.saturating_sub(len) / 2;
-        let spaces = " ".repeat(padding.saturating_sub(1));
+    fn after_key(&mut self) {
+        self.scroll();
 
-        welcome_message = format!("~{spaces}{welcome_message}");
-        welcome_message.truncate(width);
+        if self.swapped_at.elapsed() >= SWAP_INTERVAL {
+            self.update_swap();
+        }
 
-        println!("{welcome_message}\r");
+        if !mem::take(&mut self.quit_requested) && self.quit_times < self.config.quit_times {
+            self.quit_times = self.config.quit_times;
+            self.status_message = StatusMessage::from(String::new());
+        }
     }
 
-    fn move_cursor(&mut self, key: Key) {
-        let terminal_height = self.terminal.size().height as usize;
-        let Position { mut y, mut x } = self.cursor_position;
-        let height = self.document.len();
-        let mut width = if let Some(row) = self.document.row(y) {
-            row.len()
-        } else {
-            0
-        };
-
-        match key {
-            Key::Up => y = y.saturating_sub(1),
-            Key::Down => {
-                if y < height {
-                    y = y.saturating_add(1);
+    fn run_command(&mut self, command: Command) {
+        match command {
+            Command::Copy => match self.copy_content() {
+                Ok(_) => (),
+                Err(err) => self.status_message = StatusMessage::from(format!("{err}")),
+            },
+            Command::Cut => match self.cut_content() {
+                Ok(_) => (),
+                Err(err) => self.status_message = StatusMessage::from(format!("{err}")),
+            },
+            Command::Paste => match self.paste_content() {
+                Ok(text) => self.paste(&text),
+                Err(err) => {
+                    self.status_message =
+                        StatusMessage::from(format!("Failed to paste content: {err}"))
                 }
+            },
+            Command::Quit => self.quit(),
+            Command::Save => self.save(),
+            Command::Search => self.search(),
+            Command::Replace => self.replace(),
+            Command::ConvertLineEnding => self.convert_line_ending(),
+            Command::NextBuffer => self.cycle_buffer(true),
+            Command::PreviousBuffer => self.cycle_buffer(false),
+            Command::Open => self.open_buffer(),
+            Command::CloseBuffer => self.close_buffer(),
+            Command::PickBuffer => self.pick_buffer(),
+            Command::SplitStacked => self.split_pane(SplitDirection::Horizontal),
+            Command::SplitSideBySide => self.split_pane(SplitDirection::Vertical),
+            Command::ClosePane => self.close_pane(),
+            Command::NextPane => self.cycle_pane(),
+            Command::ToggleLineNumbers => self.toggle_line_numbers(),
+            Command::ToggleWrap => self.toggle_wrap(),
+            Command::Undo => self.undo(),
+            Command::Redo => self.redo(),
+            Command::ToggleMark => self.toggle_mark(),
+            Command::ClearSelection => self.clear_selection(),
+            Command::DeleteForward | Command::DeleteBackward if self.selection().is_some() => {
+                self.delete_selection()
             }
-            Key::Left => {
-                if x > 0 {
-                    x -= 1;
-                } else if y > 0 {
-                    y -= 1;
-
-                    if let Some(row) = self.document.row(y) {
-                        x = row.len();
-                    } else {
-                        x = 0;
+            Command::DeleteForward => {
+                match self.buffers[self.current]
+                    .document
+                    .delete(&self.cursor_position)
+                {
+                    Ok(_) => (),
+                    Err(err) => {
+                        self.status_message =
+                            StatusMessage::from(format!("Failed to remove content: {err}"))
                     }
                 }
             }
-            Key::Right => {
-                if x < width {
-                    x += 1;
-                } else if y < height {
-                    y += 1;
-                    x = 0;
+            Command::DeleteBackward if self.cursor_position.x > 0 || self.cursor_position.y > 0 => {
+                self.move_cursor(Key::Left);
+
+                match self.buffers[self.current]
+                    .document
+                    .delete(&self.cursor_position)
+                {
+                    Ok(_) => (),
+                    Err(err) => {
+                        self.status_message =
+                            StatusMessage::from(format!("Failed to remove character: {err}"))
+                    }
                 }
             }
-            Key::PageUp => {
-                y = if y > terminal_height {
-                    y.saturating_sub(terminal_height)
+            Command::DeleteBackward => (),
+            Command::MoveUp => self.move_to(Key::Up),
+            Command::MoveDown => self.move_to(Key::Down),
+            Command::MoveLeft => self.move_to(Key::Left),
+            Command::MoveRight => self.move_to(Key::Right),
+            Command::PageUp => self.move_to(Key::PageUp),
+            Command::PageDown => self.move_to(Key::PageDown),
+            Command::LineStart => self.move_to(Key::Home),
+            Command::LineEnd => self.move_to(Key::End),
+            Command::Palette => self.command_line(),
+            Command::WordForward => {
+                let target = emacs::forward_word(self.document(), &self.cursor_position);
+
+                self.jump_to(target);
+            }
+            Command::WordBackward => {
+                let target = emacs::backward_word(self.document(), &self.cursor_position);
+
+                self.jump_to(target);
+            }
+            Command::KillLine => self.kill_line(),
+            Command::KillRegion => self.kill_region(),
+            Command::CopyRegion => self.copy_region(),
+            Command::Yank => self.yank(),
+            Command::YankPop => self.yank_pop(),
+            Command::Goto => self.goto(),
+        }
+    }
+
+    fn command_line(&mut self) {
+        let line = self
+            .prompt_recall(PromptKind::Command, ":", |_, _, _| {})
+            .unwrap_or_default();
+
+        if let Some(line) = line {
+            match command_line::parse(&line) {
+                Ok(invocation) => self.invoke(invocation),
+                Err(err) => self.status_message = StatusMessage::from(format!("ERR: {err}")),
+            }
+        }
+    }
+
+    fn invoke(&mut self, invocation: Invocation) {
+        match invocation {
+            Invocation::Run(command) => self.run_command(command),
+            Invocation::Write(filename) => {
+                if self.document().filename.as_ref() == Some(&filename) {
+                    self.save();
                 } else {
-                    0
+                    self.write_as(filename);
                 }
             }
-            Key::PageDown => {
-                y = if y.saturating_add(terminal_height) < height {
-                    y.saturating_add(terminal_height)
-                } else {
-                    height
+            Invocation::Edit(filename) => self.open_file(filename),
+            Invocation::Goto(target) => self.go_to(target),
+            Invocation::Set(setting) => match setting {
+                Setting::TabWidth(width) => self.config.tab_width = width,
+                Setting::ExpandTabs(expand) => self.config.expand_tabs = expand,
+                Setting::Vi(on) => {
+                    if on != self.vi.is_some() {
+                        self.vi = on.then(Vi::default);
+                        self.clear_selection();
+                        self.document_mut().end_group();
+                    }
+                }
+                Setting::LineNumbers(line_numbers) => self.line_numbers = line_numbers,
+                Setting::Wrap(wrap) => {
+                    self.buffers[self.current].wrap = wrap;
+                    self.offset.x = 0;
+                }
+            },
+            Invocation::FileType(name) => {
+                if let Some(file_type) = FileType::named(&name) {
+                    self.document_mut().set_file_type(file_type);
                 }
             }
-            Key::Home => x = 0,
-            Key::End => x = width,
-            _ => (),
         }
+    }
 
-        width = if let Some(row) = self.document.row(y) {
-            row.len()
-        } else {
-            0
-        };
+    fn goto(&mut self) {
+        let text = self
+            .prompt_recall(
+                PromptKind::Goto,
+                "Go to (line[:column], +N, -N or N%): ",
+                |_, _, _| {},
+            )
+            .unwrap_or_default();
 
-        if x > width {
-            x = width;
+        if let Some(text) = text {
+            match Target::parse(&text) {
+                Some(target) => self.go_to(target),
+                None => {
+                    self.status_message =
+                        StatusMessage::from(format!("ERR: invalid position `{text}`"));
+                }
+            }
         }
-
-        self.cursor_position = Position { x, y }
     }
 
-    fn scroll(&mut self) {
-        let Position { x, y } = self.cursor_position;
-        let width = self.terminal.size().width as usize;
-        let height = self.terminal.size().height as usize;
-        let offset = &mut self.offset;
+    fn go_to(&mut self, target: Target) {
+        let position = target.resolve(self.document(), &self.cursor_position);
 
-        if y < offset.y {
-            offset.y = y;
-        } else if y >= offset.y.saturating_add(height) {
-            offset.y = y.saturating_sub(height).saturating_add(1);
-        }
+        self.jump_to(position);
+    }
 
-        if x < offset.x {
-            offset.x = x;
-        } else if x >= offset.x.saturating_add(width) {
-            offset.x = x.saturating_sub(width).saturating_add(1);
+    /// Moves the cursor, extending the selection only while marking.
+    fn move_to(&mut self, key: Key) {
+        if !self.marking {
+            self.anchor = None;
         }
-    }
 
-    fn draw_status_bar(&self) {
-        let mut status;
-        let width = self.terminal.size().width as usize;
-        let changed_indicator = if self.document.is_changed() {
-            " (changed)"
-        } else {
-            ""
-        };
-        let mut filename = "[No Name]".to_owned();
+        self.document_mut().seal_history();
+        self.move_cursor(key);
+    }
 
-        if let Some(name) = &self.document.filename {
-            filename = name.clone();
-            filename.truncate(20);
+    /// Moves the cursor to `position` like `move_to` does with a key.
+    fn jump_to(&mut self, position: Position) {
+        if !self.marking {
+            self.anchor = None;
         }
 
-        status = format!(
-            "{filename} - {} lines{changed_indicator}",
-            self.document.len(),
-        );
-        let line_indicator = format!(
-            "{} | {}/{}",
-            self.document.file_type(),
-            self.cursor_position.y.saturating_add(1),
-            self.document.len(),
-        );
-        let len = status.len() + line_indicator.len();
+        self.document_mut().seal_history();
+        self.cursor_position = position;
+        self.vi_clamp();
+    }
 
-        status.push_str(&" ".repeat(width.saturating_sub(len)));
-        status = format!("{status}{line_indicator}");
-        status.truncate(width);
-        Terminal::set_bg_color(STATUS_BG_COLOR);
-        Terminal::set_fg_color(STATUS_FG_COLOR);
-        println!("{status}\r");
-        Terminal::reset_fg_color();
-        Terminal::reset_bg_color();
+    /// Types `c`, replacing the selection in the same undo step.
+    fn insert_char(&mut self, c: char) {
+        if self.selection().is_none() {
+            self.clear_selection();
+            self.type_char(c);
+            return;
+        }
+
+        self.document_mut().begin_group();
+        self.delete_selection();
+        self.type_char(c);
+        self.document_mut().end_group();
     }
 
-    fn draw_message_bar(&self) {
-        Terminal::clear_current_line();
-        let message = &self.status_message;
+    fn type_char(&mut self, c: char) {
+        if c == '\t' && self.config.expand_tabs {
+            let Position { x, y } = self.cursor_position;
+            let tab_width = self.config.tab_width;
+            let spaces = tab_width - self.column(x, y) % tab_width;
+            let at = self.cursor_position.clone();
 
-        if message.time.elapsed() < Duration::new(5, 0) {
-            let mut text = message.text.clone();
+            self.cursor_position = self.document_mut().insert_str(&at, &" ".repeat(spaces));
+            return;
+        }
 
-            text.truncate(self.terminal.size().width as usize);
-            print!("{text}");
+        match self.buffers[self.current]
+            .document
+            .insert(&self.cursor_position, c)
+        {
+            Ok(_) => self.move_cursor(Key::Right),
+            Err(err) => {
+                self.status_message =
+                    StatusMessage::from(format!("Failed to insert character: {err}"))
+            }
         }
     }
 
-    fn prompt<C>(&mut self, prompt: &str, mut callback: C) -> Result<Option<String>, Box<dyn Err>>
-    where
-        C: FnMut(&mut Self, Key, &String),
-    {
-        let mut result = String::new();
-
-        loop {
-            self.status_message = StatusMessage::from(format!("{prompt}{result}"));
-            self.refresh_screen()?;
-
-            let key = Terminal::read_key()?;
+    /// Handles `key` in the vi layer, returning false for keys left to the
+    /// keymap.
+    fn vi_key(&mut self, key: Key) -> bool {
+        let selection = self.selection();
+        let Some(vi) = &mut self.vi else {
+            return false;
+        };
+        let mode = vi.mode;
+        let outcome = vi.key(
+            &mut self.buffers[self.current].document,
+            &mut self.cursor_position,
+            selection,
+            key,
+        );
 
-            match key {
-                Key::Backspace => result.truncate(result.len().saturating_sub(1)),
-                Key::Char('\n') => break,
-                Key::Char(c) => {
-                    if !c.is_control() {
-                        result.push(c);
-                    }
+        self.follow_vi_mode(mode);
+
+        match outcome {
+            Outcome::Unhandled => return false,
+            Outcome::Handled => return true,
+            Outcome::Undo(times) => (0..times).for_each(|_| self.undo()),
+            Outcome::Redo(times) => (0..times).for_each(|_| self.redo()),
+            Outcome::CommandLine => self.command_line(),
+            Outcome::Replay(keys) => {
+                for key in keys {
+                    self.replay_key(key);
                 }
-                Key::Esc => {
-                    result.truncate(0);
-                    break;
+
+                if let Some(vi) = &mut self.vi {
+                    vi.replaying = false;
                 }
-                _ => (),
             }
-            callback(self, key, &result);
         }
 
-        self.status_message = StatusMessage::from(String::new());
-
-        if result.is_empty() {
-            return Ok(None);
-        }
+        self.vi_clamp();
 
-        Ok(Some(result))
+        true
     }
 
-    fn save(&mut self) {
-        if self.document.filename.is_none() {
-            let new_name = self.prompt("Save as: ", |_, _, _| {}).unwrap_or_default();
+    /// Starts or clears the selection after the vi layer left `previous`
+    /// mode.
+    fn follow_vi_mode(&mut self, previous: Mode) {
+        let Some(mode) = self.vi.as_ref().map(|vi| vi.mode) else {
+            return;
+        };
+
+        if mode == previous {
+            return;
+        }
+
+        if mode == Mode::Visual {
+            self.anchor = Some(self.cursor_position.clone());
+            self.marking = true;
+        } else if previous == Mode::Visual || mode == Mode::Insert {
+            self.clear_selection();
+        }
+    }
+
+    /// Keeps the cursor on a grapheme outside of insert mode.
+    fn vi_clamp(&mut self) {
+        if let Some(vi) = &self.vi {
+            vi.clamp(
+                &self.buffers[self.current].document,
+                &mut self.cursor_position,
+            );
+        }
+    }
+
+    /// Handles a key replayed by `.`, like a single key press.
+    fn replay_key(&mut self, key: Key) {
+        if self.vi_key(key) {
+            return;
+        }
+
+        match self.keymap.lookup(&[key]) {
+            Lookup::Command(Command::Quit) | Lookup::Prefix => (),
+            Lookup::Command(command) => self.run_command(command),
+            Lookup::Unbound => {
+                if let Key::Char(c) = key {
+                    self.insert_char(c);
+                }
+            }
+        }
+    }
+
+    /// Quits, unless buffers have unsaved changes and quit has not been
+    /// asked for `quit_times` more times in a row.
+    fn quit(&mut self) {
+        self.quit_requested = true;
+
+        let changed = self
+            .buffers
+            .iter()
+            .filter(|buffer| buffer.document.is_changed())
+            .count();
+
+        if self.quit_times > 0 && changed > 0 {
+            let what = if self.buffers.len() == 1 {
+                "File has".to_owned()
+            } else if changed == 1 {
+                "1 buffer has".to_owned()
+            } else {
+                format!("{changed} buffers have")
+            };
+
+            let quit = self
+                .keymap
+                .binding(Command::Quit)
+                .unwrap_or_else(|| "quit".to_owned());
+
+            self.status_message = StatusMessage::from(format!(
+                "WARNING! {what} unsaved changes. Press {quit} {} more time to quit.",
+                self.quit_times
+            ));
+            self.quit_times -= 1;
+
+            return;
+        }
+        self.should_quit = true;
+    }
+
+    fn refresh_screen(&mut self) -> Result<(), Box<dyn Err>> {
+        Terminal::cursor_hide();
+        Terminal::cursor_position(&Position::default());
+
+        if self.should_quit {
+            Terminal::clear_screen();
+        } else {
+            self.store_pane();
+            self.sync_panes();
+
+            let rects = self.layout.rects(self.screen_area());
+
+            for (index, rect) in &rects {
+                let pane = &self.panes[*index];
+                let until = pane.offset.y.saturating_add(rect.height as usize);
+
+                self.buffers[pane.buffer]
+                    .document
+                    .highlight(self.highlighted_word.as_ref(), Some(until))?;
+            }
+
+            for (index, rect) in &rects {
+                self.draw_pane(&self.panes[*index], rect, *index == self.active);
+            }
+
+            self.draw_separators();
+            self.draw_message_bar();
+
+            let rect = self.active_rect();
+            let gutter = self.gutter_width(self.document(), rect.width);
+
+            if let Some(x) = self.prompt_cursor {
+                Terminal::cursor_position(&Position {
+                    x,
+                    y: self.screen_area().height as usize,
+                });
+            } else {
+                let Position { x, y } = self.cursor_on_screen();
+
+                Terminal::cursor_position(&Position {
+                    x: x.saturating_add(rect.x as usize + gutter),
+                    y: y.saturating_add(rect.y as usize),
+                });
+            }
+        }
+
+        Terminal::cursor_show();
+
+        match Terminal::flush() {
+            Ok(_) => Ok(()),
+            Err(err) => Err(Box::new(err)),
+        }
+    }
+
+    fn draw_pane(&self, pane: &Pane, rect: &Rect, active: bool) {
+        let height = rect.height.saturating_sub(1);
+        let document = &self.buffers[pane.buffer].document;
+        let gutter = self.gutter_width(document, rect.width);
+        let width = (rect.width as usize).saturating_sub(gutter);
+        let lines = self.visual_lines(pane, width, height as usize);
+
+        for (terminal_row, (y, columns, first)) in (0..height).zip(lines) {
+            Terminal::cursor_position(&Position {
+                x: rect.x as usize,
+                y: rect.y.saturating_add(terminal_row) as usize,
+            });
+
+            if first {
+                self.draw_line_number(y, pane.cursor_position.y, document, gutter);
+            } else {
+                print!("{:gutter$}", "");
+            }
+
+            if let Some(row) = document.row(y) {
+                let selection = if active {
+                    self.selection_in_row(y)
+                } else {
+                    0..0
+                };
+
+                self.draw_row(row, columns, width, &selection);
+            } else if document.is_empty() && terminal_row == height / 3 {
+                self.draw_welcome_message(width);
+            } else {
+                print!("{:width$}", "~");
+            }
+        }
+
+        if rect.height > 0 {
+            Terminal::cursor_position(&Position {
+                x: rect.x as usize,
+                y: rect.y.saturating_add(height) as usize,
+            });
+            self.draw_status_bar(pane, rect.width as usize, active);
+        }
+    }
+
+    /// Returns the row, the screen columns of it shown and whether they
+    /// start the row, for each of the `height` lines of `pane` with `width`
+    /// columns for text.
+    fn visual_lines(
+        &self,
+        pane: &Pane,
+        width: usize,
+        height: usize,
+    ) -> Vec<(usize, Range<usize>, bool)> {
+        let buffer = &self.buffers[pane.buffer];
+        let mut lines = Vec::with_capacity(height);
+
+        if buffer.wrap == Wrap::Off {
+            for y in pane.offset.y..pane.offset.y.saturating_add(height) {
+                lines.push((y, pane.offset.x..pane.offset.x.saturating_add(width), true));
+            }
+
+            return lines;
+        }
+
+        let mut y = pane.offset.y;
+        let mut segment = pane.offset.x;
+
+        while lines.len() < height {
+            let starts = self.segments(buffer, y, width);
+
+            for (index, start) in starts.iter().enumerate().skip(segment) {
+                let columns = buffer.document.row(y).map_or(0..0, |row| {
+                    let end = starts.get(index + 1).copied().unwrap_or(row.len());
+
+                    row.column(*start, self.config.tab_width)
+                        ..row.column(end, self.config.tab_width)
+                });
+
+                lines.push((y, columns, index == 0));
+            }
+
+            segment = 0;
+            y = y.saturating_add(1);
+        }
+
+        lines.truncate(height);
+        lines
+    }
+
+    /// Start of each visual line of row `y` of `buffer` in `width` columns.
+    fn segments(&self, buffer: &Buffer, y: usize, width: usize) -> Vec<usize> {
+        match buffer.document.row(y) {
+            Some(row) if buffer.wrap != Wrap::Off => {
+                row.wrap(width, buffer.wrap == Wrap::Words, self.config.tab_width)
+            }
+            _ => vec![0],
+        }
+    }
+
+    fn current_segments(&self, y: usize) -> Vec<usize> {
+        let width = self.view_size().width as usize;
+
+        self.segments(&self.buffers[self.current], y, width)
+    }
+
+    /// Screen column of grapheme `x` in row `y` of the current buffer.
+    fn column(&self, x: usize, y: usize) -> usize {
+        self.document()
+            .row(y)
+            .map_or(0, |row| row.column(x, self.config.tab_width))
+    }
+
+    /// Returns the cursor as a row and the visual line of that row it is on.
+    fn cursor_segment(&self) -> (usize, usize) {
+        let Position { x, y } = self.cursor_position;
+
+        (y, segment_of(&self.current_segments(y), x))
+    }
+
+    /// Where the cursor is relative to the top left corner of the active pane.
+    fn cursor_on_screen(&self) -> Position {
+        let Position { x, y } = self.cursor_position;
+
+        if self.buffers[self.current].wrap == Wrap::Off {
+            return Position {
+                x: self.column(x, y).saturating_sub(self.offset.x),
+                y: y.saturating_sub(self.offset.y),
+            };
+        }
+
+        let starts = self.current_segments(y);
+        let segment = segment_of(&starts, x);
+        let mut lines = segment;
+
+        for row in self.offset.y..y {
+            lines += self.current_segments(row).len();
+        }
+
+        Position {
+            x: self
+                .column(x, y)
+                .saturating_sub(self.column(starts[segment], y)),
+            y: lines.saturating_sub(self.offset.x),
+        }
+    }
+
+    fn toggle_wrap(&mut self) {
+        let buffer = &mut self.buffers[self.current];
+        let (wrap, message) = match buffer.wrap {
+            Wrap::Off => (Wrap::Words, "Soft wrap at words."),
+            Wrap::Words => (Wrap::Anywhere, "Soft wrap anywhere."),
+            Wrap::Anywhere => (Wrap::Off, "Soft wrap off."),
+        };
+
+        buffer.wrap = wrap;
+        self.offset.x = 0;
+        self.status_message = StatusMessage::from(message.to_owned());
+    }
+
+    /// Columns taken by line numbers in a pane `width` wide showing `document`.
+    fn gutter_width(&self, document: &Document, width: u16) -> usize {
+        if self.line_numbers == LineNumbers::Off {
+            return 0;
+        }
+
+        let gutter = document.len().max(1).to_string().len() + 1;
+
+        if gutter + MIN_PANE_WIDTH as usize > width as usize {
+            return 0;
+        }
+
+        gutter
+    }
+
+    fn draw_line_number(&self, y: usize, cursor_y: usize, document: &Document, gutter: usize) {
+        if gutter == 0 {
+            return;
+        }
+
+        let number_width = gutter - 1;
+
+        if y >= document.len() {
+            print!("{:gutter$}", "");
+            return;
+        }
+
+        let number = match self.line_numbers {
+            LineNumbers::Relative if y != cursor_y => y.abs_diff(cursor_y),
+            _ => y.saturating_add(1),
+        };
+
+        Terminal::set_fg_color(self.config.colors.gutter);
+        print!("{number:>number_width$} ");
+        Terminal::reset_fg_color();
+    }
+
+    fn toggle_line_numbers(&mut self) {
+        let (line_numbers, message) = match self.line_numbers {
+            LineNumbers::Off => (LineNumbers::Absolute, "Line numbers on."),
+            LineNumbers::Absolute => (LineNumbers::Relative, "Relative line numbers on."),
+            LineNumbers::Relative => (LineNumbers::Off, "Line numbers off."),
+        };
+
+        self.line_numbers = line_numbers;
+        self.status_message = StatusMessage::from(message.to_owned());
+    }
+
+    fn draw_separators(&self) {
+        for separator in self.layout.separators(self.screen_area()) {
+            for y in separator.y..separator.y.saturating_add(separator.height) {
+                Terminal::cursor_position(&Position {
+                    x: separator.x as usize,
+                    y: y as usize,
+                });
+                Terminal::set_fg_color(self.config.colors.status_bg);
+                print!("│");
+                Terminal::reset_fg_color();
+            }
+        }
+    }
+
+    fn draw_welcome_message(&self, width: usize) {
+        let mut welcome_message = format!("Revise | v{VERSION}");
+        let len = welcome_message.len();
+        let padding = width.saturating_sub(len) / 2;
+        let spaces = " ".repeat(padding.saturating_sub(1));
+
+        welcome_message = format!("~{spaces}{welcome_message}");
+        welcome_message.truncate(width);
+
+        print!("{welcome_message:width$}");
+    }
+
+    fn move_cursor(&mut self, key: Key) {
+        if self.buffers[self.current].wrap != Wrap::Off
+            && matches!(key, Key::Up | Key::Down | Key::PageUp | Key::PageDown)
+        {
+            self.move_visual(key);
+            return;
+        }
+
+        let terminal_height = self.view_size().height as usize;
+        let Position { mut y, mut x } = self.cursor_position;
+        let height = self.document().len();
+        let mut width = if let Some(row) = self.document().row(y) {
+            row.len()
+        } else {
+            0
+        };
+
+        match key {
+            Key::Up => y = y.saturating_sub(1),
+            Key::Down if y < height => {
+                y = y.saturating_add(1);
+            }
+            Key::Left => {
+                if x > 0 {
+                    x -= 1;
+                } else if y > 0 {
+                    y -= 1;
+
+                    if let Some(row) = self.document().row(y) {
+                        x = row.len();
+                    } else {
+                        x = 0;
+                    }
+                }
+            }
+            Key::Right => {
+                if x < width {
+                    x += 1;
+                } else if y < height {
+                    y += 1;
+                    x = 0;
+                }
+            }
+            Key::PageUp => {
+                y = if y > terminal_height {
+                    y.saturating_sub(terminal_height)
+                } else {
+                    0
+                }
+            }
+            Key::PageDown => {
+                y = if y.saturating_add(terminal_height) < height {
+                    y.saturating_add(terminal_height)
+                } else {
+                    height
+                }
+            }
+            Key::Home => x = 0,
+            Key::End => x = width,
+            _ => (),
+        }
+
+        width = if let Some(row) = self.document().row(y) {
+            row.len()
+        } else {
+            0
+        };
+
+        if x > width {
+            x = width;
+        }
+
+        self.cursor_position = Position { x, y }
+    }
+
+    /// Moves the cursor up or down by visual lines, keeping its column in
+    /// the line.
+    fn move_visual(&mut self, key: Key) {
+        let count = match key {
+            Key::PageUp | Key::PageDown => self.view_size().height as usize,
+            _ => 1,
+        };
+        let up = matches!(key, Key::Up | Key::PageUp);
+        let Position { x, mut y } = self.cursor_position;
+        let mut starts = self.current_segments(y);
+        let mut segment = segment_of(&starts, x);
+        let column = self.column(x, y) - self.column(starts[segment], y);
+
+        for _ in 0..count {
+            if up && segment > 0 {
+                segment -= 1;
+            } else if up && y > 0 {
+                y -= 1;
+                starts = self.current_segments(y);
+                segment = starts.len() - 1;
+            } else if !up && segment + 1 < starts.len() {
+                segment += 1;
+            } else if !up && y < self.document().len() {
+                y += 1;
+                starts = self.current_segments(y);
+                segment = 0;
+            } else {
+                break;
+            }
+        }
+
+        let len = self.document().row(y).map_or(0, Row::len);
+        let end = starts.get(segment + 1).map_or(len, |next| next - 1);
+        let x = self.document().row(y).map_or(0, |row| {
+            let column = row.column(starts[segment], self.config.tab_width) + column;
+
+            row.grapheme_at_column(column, self.config.tab_width)
+        });
+
+        self.cursor_position = Position { x: x.min(end), y };
+    }
+
+    /// Keeps the cursor in view by visual lines when wrapping, where
+    /// `offset.x` is the first visual line of row `offset.y` shown.
+    fn scroll_wrapped(&mut self) {
+        let height = self.view_size().height as usize;
+        let cursor = self.cursor_segment();
+        let segments = self.current_segments(self.offset.y).len();
+        let mut top = (self.offset.y, self.offset.x.min(segments - 1));
+
+        if cursor < top {
+            top = cursor;
+        } else {
+            let (mut y, mut segment) = cursor;
+
+            for _ in 1..height {
+                if segment > 0 {
+                    segment -= 1;
+                } else if y > 0 {
+                    y -= 1;
+                    segment = self.current_segments(y).len() - 1;
+                } else {
+                    break;
+                }
+            }
+
+            top = top.max((y, segment));
+        }
+
+        self.offset = Position { x: top.1, y: top.0 };
+    }
+
+    fn scroll(&mut self) {
+        if self.buffers[self.current].wrap != Wrap::Off {
+            self.scroll_wrapped();
+            return;
+        }
+
+        let Position { x, y } = self.cursor_position;
+        let Size { width, height } = self.view_size();
+        let width = width as usize;
+        let height = height as usize;
+        let column = self.column(x, y);
+        let cursor_width = self.column(x + 1, y).saturating_sub(column).max(1);
+        let offset = &mut self.offset;
+
+        if y < offset.y {
+            offset.y = y;
+        } else if y >= offset.y.saturating_add(height) {
+            offset.y = y.saturating_sub(height).saturating_add(1);
+        }
+
+        if column < offset.x {
+            offset.x = column;
+        } else if column + cursor_width > offset.x.saturating_add(width) {
+            offset.x = (column + cursor_width).saturating_sub(width);
+        }
+    }
+
+    fn draw_status_bar(&self, pane: &Pane, width: usize, active: bool) {
+        let mut status;
+        let document = &self.buffers[pane.buffer].document;
+        let changed_indicator = if document.is_changed() {
+            " (changed)"
+        } else {
+            ""
+        };
+        let mut filename = "[No Name]".to_owned();
+
+        if let Some(name) = &document.filename {
+            filename = name.clone();
+            filename.truncate(20);
+        }
+
+        let buffer_indicator = if self.buffers.len() > 1 {
+            format!("[{}/{}] ", pane.buffer + 1, self.buffers.len())
+        } else {
+            String::new()
+        };
+
+        let mode_indicator = match &self.vi {
+            Some(vi) if active => format!("{} ", vi.mode.name()),
+            _ => String::new(),
+        };
+
+        status = format!(
+            "{mode_indicator}{buffer_indicator}{filename} - {} lines{changed_indicator}",
+            document.len(),
+        );
+        let final_newline_indicator = if document.has_final_newline() {
+            ""
+        } else {
+            " [noeol]"
+        };
+        let mixed_indicator = if document.has_mixed_line_endings() {
+            " (mixed)"
+        } else {
+            ""
+        };
+        let line_indicator = format!(
+            "{} | {}{mixed_indicator}{final_newline_indicator} | {}/{}",
+            document.file_type(),
+            document.line_ending(),
+            pane.cursor_position.y.saturating_add(1),
+            document.len(),
+        );
+        let len = status.len() + line_indicator.len();
+
+        status.push_str(&" ".repeat(width.saturating_sub(len)));
+        status = format!("{status}{line_indicator}");
+        status.truncate(width);
+
+        if active {
+            Terminal::set_bg_color(self.config.colors.status_bg);
+        } else {
+            Terminal::set_bg_color(self.config.colors.inactive_status_bg);
+        }
+
+        Terminal::set_fg_color(self.config.colors.status_fg);
+        print!("{status}");
+        Terminal::reset_fg_color();
+        Terminal::reset_bg_color();
+    }
+
+    fn draw_message_bar(&self) {
+        let area = self.screen_area();
+
+        Terminal::cursor_position(&Position {
+            x: 0,
+            y: area.height as usize,
+        });
+        Terminal::clear_current_line();
+        let message = &self.status_message;
+
+        if message.time.elapsed() < self.config.message_timeout() {
+            let mut text = message.text.clone();
+
+            text.truncate(self.terminal.size().width as usize);
+            print!("{text}");
+        }
+    }
+
+    fn prompt<C>(&mut self, prompt: &str, callback: C) -> Result<Option<String>, Box<dyn Err>>
+    where
+        C: FnMut(&mut Self, Key, &str),
+    {
+        let result = self.prompt_line(prompt, None, callback)?;
+
+        Ok(result.filter(|result| !result.is_empty()))
+    }
+
+    /// Like `prompt`, but offers the previous answers to `kind` with Up and
+    /// Down, and remembers this one.
+    fn prompt_recall<C>(
+        &mut self,
+        kind: PromptKind,
+        prompt: &str,
+        callback: C,
+    ) -> Result<Option<String>, Box<dyn Err>>
+    where
+        C: FnMut(&mut Self, Key, &str),
+    {
+        let result = self.prompt_line(prompt, Some(kind), callback)?;
+
+        Ok(result.filter(|result| !result.is_empty()))
+    }
+
+    /// Reads a line in the message bar. Only returns `None` when cancelled
+    /// with ESC, so an empty answer can be told apart.
+    fn prompt_line<C>(
+        &mut self,
+        prompt: &str,
+        kind: Option<PromptKind>,
+        mut callback: C,
+    ) -> Result<Option<String>, Box<dyn Err>>
+    where
+        C: FnMut(&mut Self, Key, &str),
+    {
+        let entries = kind
+            .map(|kind| self.prompt_history.entries(kind).to_vec())
+            .unwrap_or_default();
+        let mut recalled = entries.len();
+        let mut draft = String::new();
+        let mut line = LineEditor::default();
+        let mut cancelled = false;
+
+        'prompt: loop {
+            self.status_message =
+                StatusMessage::from(format!("{prompt}{}  {}", line.text(), self.prompt_notice));
+            self.prompt_cursor = Some(prompt.chars().count() + line.cursor());
+            self.refresh_screen()?;
+
+            let keys = match self.terminal.read_input()? {
+                Input::Key(key) | Input::Shift(key) => vec![key],
+                Input::Resize => {
+                    self.resize()?;
+                    continue;
+                }
+                Input::Paste(text) => text
+                    .chars()
+                    .filter(|c| !c.is_control())
+                    .map(Key::Char)
+                    .collect(),
+            };
+
+            for key in keys {
+                match key {
+                    Key::Char('\n') => break 'prompt,
+                    // Ctrl-G is how emacs users cancel.
+                    Key::Esc | Key::Ctrl('g') => {
+                        line.clear();
+                        cancelled = true;
+                        break 'prompt;
+                    }
+                    Key::Char(c) if !c.is_control() => line.insert(c),
+                    Key::Backspace => line.backspace(),
+                    Key::Delete => line.delete(),
+                    Key::Ctrl('w') => line.delete_word(),
+                    Key::Left => line.left(),
+                    Key::Right => line.right(),
+                    Key::Home => line.home(),
+                    Key::End => line.end(),
+                    Key::Char('\t') => self.complete(kind, &mut line),
+                    Key::Up if recalled > 0 => {
+                        if recalled == entries.len() {
+                            line.text().clone_into(&mut draft);
+                        }
+
+                        recalled -= 1;
+                        line.set(&entries[recalled]);
+                    }
+                    Key::Down if recalled < entries.len() => {
+                        recalled += 1;
+                        line.set(entries.get(recalled).unwrap_or(&draft));
+                    }
+                    _ => (),
+                }
+                callback(self, key, line.text());
+            }
+        }
+
+        self.status_message = StatusMessage::from(String::new());
+        self.prompt_notice.clear();
+        self.prompt_cursor = None;
+
+        if cancelled {
+            return Ok(None);
+        }
+
+        if let Some(kind) = kind {
+            if let Err(err) = self.prompt_history.push(kind, line.text()) {
+                self.status_message =
+                    StatusMessage::from(format!("Could not write prompt history: {err}"));
+            }
+        }
+
+        Ok(Some(line.text().to_owned()))
+    }
+
+    /// Completes a command or file name as far as it is unambiguous, and
+    /// lists the candidates when there are several.
+    fn complete(&mut self, kind: Option<PromptKind>, line: &mut LineEditor) {
+        let candidates = match kind {
+            Some(PromptKind::Command) => command_line::complete(line.text()),
+            Some(PromptKind::File) => command_line::complete_path(line.text()),
+            _ => return,
+        };
+        let common = command_line::common_prefix(&candidates);
+
+        if common.len() > line.text().len() {
+            line.set(common);
+        }
+
+        self.prompt_notice = match candidates.len() {
+            0 => "[No match]".to_owned(),
+            1 => String::new(),
+            _ => {
+                let start = common.rfind([' ', '/']).map_or(0, |index| index + 1);
+
+                candidates
+                    .iter()
+                    .map(|candidate| &candidate[start..])
+                    .collect::<Vec<_>>()
+                    .join(" ")
+            }
+        };
+    }
+
+    fn save(&mut self) {
+        if self.document().filename.is_none() {
+            let new_name = self
+                .prompt_recall(PromptKind::File, "Save as: ", |_, _, _| {})
+                .unwrap_or_default();
 
             if new_name.is_none() {
                 self.status_message = StatusMessage::from("Save aborted.".to_owned());
                 return;
             }
-            self.document.filename = new_name;
+            self.document_mut().filename = new_name;
+        }
+
+        if self.document().changed_on_disk() && !self.resolve_disk_change() {
+            return;
+        }
+
+        self.write_document();
+    }
+
+    /// Saves the document, returning whether the file was written.
+    fn write_document(&mut self) -> bool {
+        match self.document_mut().save() {
+            Ok(None) => {
+                self.buffers[self.current].remove_swap();
+                self.status_message = StatusMessage::from("File saved successfully.".to_owned());
+                true
+            }
+            Ok(Some(err)) => {
+                self.buffers[self.current].remove_swap();
+                self.status_message = StatusMessage::from(format!(
+                    "WARNING: File saved, but syncing its directory failed: {err}"
+                ));
+                true
+            }
+            Err(err) => {
+                self.status_message = StatusMessage::from(format!("Error writing file: {err}"));
+                false
+            }
+        }
+    }
+
+    /// Writes the document to `filename` and renames the buffer after it,
+    /// keeping the old name if the write fails.
+    fn write_as(&mut self, filename: String) {
+        if Path::new(&filename).exists() {
+            let answer = self
+                .prompt(
+                    &format!("{filename} already exists. Overwrite? (y/n): "),
+                    |_, _, _| {},
+                )
+                .unwrap_or_default();
+
+            if answer.as_deref().map(str::trim) != Some("y") {
+                self.status_message = StatusMessage::from("Write aborted.".to_owned());
+                return;
+            }
+        }
+
+        let previous = self.document_mut().filename.replace(filename);
+
+        if !self.write_document() {
+            self.document_mut().filename = previous;
+        }
+    }
+
+    /// Tells whether the user paused typing with no chord or vi command
+    /// half typed, so a prompt would not take keys meant for the document.
+    fn is_idle(&self) -> bool {
+        self.pending_keys.is_empty()
+            && self.vi.as_ref().is_none_or(|vi| vi.pending.is_empty())
+            && self.typed_at.elapsed() >= TYPING_PAUSE
+    }
+
+    /// Looks for files changed on disk, the current one first, and asks
+    /// about the first one found after switching to its buffer.
+    fn check_disk(&mut self) {
+        self.disk_checked_at = Instant::now();
+
+        let changed = (0..self.buffers.len())
+            .map(|offset| (self.current + offset) % self.buffers.len())
+            .find(|index| self.buffers[*index].document.has_new_disk_change());
+
+        let Some(index) = changed else {
+            return;
+        };
+
+        if index != self.current {
+            self.switch_buffer(index);
+            self.scroll();
+        }
+
+        if self.resolve_disk_change() {
+            self.write_document();
+        }
+    }
+
+    /// Asks what to do about the file having been changed on disk by
+    /// someone else, and returns whether the buffer should overwrite it.
+    fn resolve_disk_change(&mut self) -> bool {
+        loop {
+            let question = format!(
+                "{} changed on disk. (r)eload, (o)verwrite, (d)iff or ESC to keep editing: ",
+                self.buffers[self.current].name()
+            );
+            let answer = self.prompt(&question, |_, _, _| {}).unwrap_or_default();
+
+            match answer.as_deref().map(str::trim) {
+                Some("r") => {
+                    self.reload();
+                    return false;
+                }
+                Some("o") => return true,
+                Some("d") => {
+                    self.status_message = StatusMessage::from(self.disk_diff());
+
+                    if self.refresh_screen().is_err() || self.read_key().is_err() {
+                        return false;
+                    }
+                }
+                _ => {
+                    self.document_mut().ignore_disk_change();
+                    self.status_message = StatusMessage::from(
+                        "Keeping the buffer, saving will ask again.".to_owned(),
+                    );
+                    return false;
+                }
+            }
+        }
+    }
+
+    fn reload(&mut self) {
+        match self.document_mut().reload() {
+            Ok(_) => {
+                let Position { mut x, mut y } = self.cursor_position;
+
+                y = y.min(self.document().len());
+                x = x.min(self.document().row(y).map_or(0, Row::len));
+                self.cursor_position = Position { x, y };
+                self.scroll();
+                self.status_message = StatusMessage::from("File reloaded from disk.".to_owned());
+            }
+            Err(err) => {
+                self.status_message = StatusMessage::from(format!("Could not reload file: {err}"))
+            }
+        }
+    }
+
+    fn disk_diff(&self) -> String {
+        let Some(filename) = &self.document().filename else {
+            return String::new();
+        };
+
+        match fs::read_to_string(filename) {
+            Ok(contents) => {
+                let (lines, _) = LineEnding::split(&contents);
+                let hunks = self.document().diff(&lines);
+
+                if hunks.is_empty() {
+                    String::from("File on disk matches the buffer.")
+                } else {
+                    format!(
+                        "File on disk differs from the buffer at {}.",
+                        diff::describe(&hunks)
+                    )
+                }
+            }
+            Err(err) => format!("Could not read file on disk: {err}"),
+        }
+    }
+
+    fn offer_recovery(&mut self, swap: Swap) -> Result<(), Box<dyn Err>> {
+        let question = format!(
+            "Swap file found for {}. (r)ecover, (d)iff, (x) discard or ESC to keep it: ",
+            swap.filename
+        );
+
+        loop {
+            let answer = self.prompt(&question, |_, _, _| {})?;
+
+            match answer.as_deref().map(str::trim) {
+                Some("r") => {
+                    self.document_mut().recover(swap);
+                    self.status_message =
+                        StatusMessage::from("Recovered unsaved changes from swap file.".to_owned());
+                }
+                Some("d") => {
+                    let diff = swap.diff(self.document());
+
+                    self.status_message = StatusMessage::from(diff);
+                    self.refresh_screen()?;
+                    self.read_key()?;
+                    continue;
+                }
+                Some("x") => {
+                    Swap::remove(&swap.path);
+                    self.status_message = StatusMessage::from("Swap file discarded.".to_owned());
+                }
+                _ => {
+                    self.buffers[self.current].kept_swap = Some(swap.path);
+                    self.status_message = StatusMessage::from(
+                        "Keeping the swap file, it is left as it is.".to_owned(),
+                    );
+                }
+            }
+
+            return Ok(());
+        }
+    }
+
+    fn update_swap(&mut self) {
+        self.swapped_at = Instant::now();
+
+        for buffer in &mut self.buffers {
+            if let Err(err) = buffer.update_swap() {
+                self.status_message =
+                    StatusMessage::from(format!("Could not write swap file: {err}"));
+            }
+        }
+    }
+
+    fn document(&self) -> &Document {
+        &self.buffers[self.current].document
+    }
+
+    fn document_mut(&mut self) -> &mut Document {
+        &mut self.buffers[self.current].document
+    }
+
+    /// Parks the cursor of the current buffer and restores the one of `index`.
+    fn switch_buffer(&mut self, index: usize) {
+        if index >= self.buffers.len() {
+            return;
+        }
+
+        let buffer = &mut self.buffers[self.current];
+
+        buffer.cursor_position = self.cursor_position.clone();
+        buffer.offset = self.offset.clone();
+        self.current = index;
+        self.panes[self.active].buffer = index;
+        self.clear_selection();
+
+        let buffer = &self.buffers[self.current];
+
+        self.cursor_position = buffer.cursor_position.clone();
+        self.offset = buffer.offset.clone();
+    }
+
+    fn screen_area(&self) -> Rect {
+        let size = self.terminal.size();
+
+        Rect {
+            x: 0,
+            y: 0,
+            width: size.width,
+            height: size.height.saturating_add(1),
+        }
+    }
+
+    fn active_rect(&self) -> Rect {
+        self.layout
+            .rects(self.screen_area())
+            .into_iter()
+            .find(|(index, _)| *index == self.active)
+            .map(|(_, rect)| rect)
+            .unwrap_or_default()
+    }
+
+    /// Size of the text area of the active pane, without its status bar.
+    fn view_size(&self) -> Size {
+        let rect = self.active_rect();
+        let gutter = self.gutter_width(self.document(), rect.width);
+
+        Size {
+            width: rect.width.saturating_sub(gutter as u16),
+            height: rect.height.saturating_sub(1),
+        }
+    }
+
+    /// Copies the cursor of the active pane into the pane list.
+    fn store_pane(&mut self) {
+        let pane = &mut self.panes[self.active];
+
+        pane.buffer = self.current;
+        pane.cursor_position = self.cursor_position.clone();
+        pane.offset = self.offset.clone();
+    }
+
+    /// Scrolls every pane so its cursor stays in view, as after a resize.
+    fn scroll_panes(&mut self) {
+        let active = self.active;
+
+        self.store_pane();
+
+        for index in (0..self.panes.len()).filter(|index| *index != active) {
+            let pane = &self.panes[index];
+
+            self.active = index;
+            self.current = pane.buffer;
+            self.cursor_position = pane.cursor_position.clone();
+            self.offset = pane.offset.clone();
+            self.scroll();
+            self.store_pane();
+        }
+
+        let pane = &self.panes[active];
+
+        self.active = active;
+        self.current = pane.buffer;
+        self.cursor_position = pane.cursor_position.clone();
+        self.offset = pane.offset.clone();
+        self.scroll();
+    }
+
+    /// Moves the panes other than the active one along with the rows added
+    /// or removed through it, keeping them within their documents.
+    fn sync_panes(&mut self) {
+        for (index, buffer) in self.buffers.iter_mut().enumerate() {
+            let shifts = buffer.document.take_row_shifts();
+
+            for (pane_index, pane) in self.panes.iter_mut().enumerate() {
+                if pane.buffer != index || pane_index == self.active {
+                    continue;
+                }
+
+                for &(y, delta) in &shifts {
+                    pane.shift_rows(y, delta);
+                }
+
+                pane.clamp(&buffer.document);
+            }
+        }
+    }
+
+    fn focus_pane(&mut self, index: usize) {
+        self.store_pane();
+        self.load_pane(index);
+    }
+
+    /// Makes `index` the active pane, clamping its cursor to the document
+    /// in case the buffer was edited from another pane.
+    fn load_pane(&mut self, index: usize) {
+        self.active = index;
+        self.clear_selection();
+
+        let pane = &self.panes[index];
+        let document = &self.buffers[pane.buffer].document;
+        let y = pane.cursor_position.y.min(document.len());
+        let x = pane
+            .cursor_position
+            .x
+            .min(document.row(y).map_or(0, Row::len));
+
+        self.current = pane.buffer;
+        self.cursor_position = Position { x, y };
+        self.offset = pane.offset.clone();
+        self.scroll();
+    }
+
+    fn split_pane(&mut self, direction: SplitDirection) {
+        let rect = self.active_rect();
+        let too_small = match direction {
+            SplitDirection::Horizontal => rect.height < 2 * MIN_PANE_HEIGHT,
+            SplitDirection::Vertical => rect.width < 2 * MIN_PANE_WIDTH + 1,
+        };
+
+        if too_small {
+            self.status_message = StatusMessage::from("Pane is too small to split.".to_owned());
+            return;
         }
 
-        if self.document.save().is_ok() {
-            self.status_message = StatusMessage::from("File saved successfully.".to_owned());
+        self.store_pane();
+
+        let index = self.panes.len();
+
+        self.panes.push(self.panes[self.active].clone());
+        self.layout.split(self.active, index, direction);
+        self.focus_pane(index);
+    }
+
+    fn close_pane(&mut self) {
+        if self.panes.len() < 2 {
+            self.status_message = StatusMessage::from("Cannot close the last pane.".to_owned());
+            return;
+        }
+
+        let closed = self.active;
+
+        self.layout.remove(closed);
+        self.panes.remove(closed);
+        self.load_pane(closed.min(self.panes.len() - 1));
+    }
+
+    fn cycle_pane(&mut self) {
+        let order = self
+            .layout
+            .rects(self.screen_area())
+            .into_iter()
+            .map(|(index, _)| index)
+            .collect::<Vec<_>>();
+
+        if let Some(position) = order.iter().position(|index| *index == self.active) {
+            self.focus_pane(order[(position + 1) % order.len()]);
+        }
+    }
+
+    fn cycle_buffer(&mut self, forward: bool) {
+        let len = self.buffers.len();
+
+        if len < 2 {
+            self.status_message = StatusMessage::from("No other buffer.".to_owned());
+            return;
+        }
+
+        let index = if forward {
+            (self.current + 1) % len
         } else {
-            self.status_message = StatusMessage::from("Error writing file!".to_owned());
+            (self.current + len - 1) % len
+        };
+
+        self.switch_buffer(index);
+        self.status_message = StatusMessage::from(format!(
+            "Buffer {}/{len}: {}",
+            index + 1,
+            self.buffers[index].name()
+        ));
+    }
+
+    fn open_buffer(&mut self) {
+        let filename = self
+            .prompt_recall(PromptKind::File, "Open: ", |_, _, _| {})
+            .unwrap_or_default();
+
+        if let Some(filename) = filename {
+            self.open_file(filename);
+        }
+    }
+
+    fn open_file(&mut self, filename: String) {
+        if let Some(index) = self
+            .buffers
+            .iter()
+            .position(|buffer| buffer.document.filename.as_deref() == Some(&filename))
+        {
+            self.switch_buffer(index);
+            return;
+        }
+
+        match Document::open(&filename) {
+            Ok(document) => {
+                let mut buffer = Buffer::new(document);
+                let warning = buffer.check_swap();
+
+                buffer.document.set_backup(self.document().has_backup());
+                self.buffers.push(buffer);
+                self.switch_buffer(self.buffers.len() - 1);
+
+                if let Some(swap) = self.buffers[self.current].pending_swap.take() {
+                    if let Err(err) = self.offer_recovery(swap) {
+                        self.status_message = StatusMessage::from(format!("{err}"));
+                    }
+                } else if let Some(warning) = warning {
+                    self.status_message = StatusMessage::from(warning);
+                }
+            }
+            Err(err) => {
+                self.status_message =
+                    StatusMessage::from(format!("ERR: Could not open file: {filename}: {err}"))
+            }
+        }
+    }
+
+    fn close_buffer(&mut self) {
+        if self.document().is_changed() {
+            let answer = self
+                .prompt(
+                    "Buffer has unsaved changes. Close anyway? (y/n): ",
+                    |_, _, _| {},
+                )
+                .unwrap_or_default();
+
+            if answer.as_deref().map(str::trim) != Some("y") {
+                self.status_message = StatusMessage::from("Close aborted.".to_owned());
+                return;
+            }
+        }
+
+        let closed = self.current;
+        let mut buffer = self.buffers.remove(closed);
+
+        buffer.remove_swap();
+
+        if self.buffers.is_empty() {
+            self.buffers.push(Buffer::default());
+        }
+
+        self.current = closed.min(self.buffers.len() - 1);
+
+        for pane in &mut self.panes {
+            if pane.buffer == closed {
+                pane.buffer = self.current;
+                pane.cursor_position = self.buffers[self.current].cursor_position.clone();
+                pane.offset = self.buffers[self.current].offset.clone();
+            } else if pane.buffer > closed {
+                pane.buffer -= 1;
+            }
+        }
+
+        let buffer = &self.buffers[self.current];
+
+        self.cursor_position = buffer.cursor_position.clone();
+        self.offset = buffer.offset.clone();
+        self.status_message = StatusMessage::from(format!("Closed {}.", buffer.name()));
+    }
+
+    fn pick_buffer(&mut self) {
+        let list = self
+            .buffers
+            .iter()
+            .enumerate()
+            .map(|(index, buffer)| {
+                let changed = if buffer.document.is_changed() {
+                    "*"
+                } else {
+                    ""
+                };
+
+                format!("{}:{}{changed}", index + 1, buffer.name())
+            })
+            .collect::<Vec<_>>()
+            .join(" ");
+        let answer = self
+            .prompt(&format!("Buffer ({list}): "), |_, _, _| {})
+            .unwrap_or_default();
+
+        if let Some(answer) = answer {
+            let answer = answer.trim();
+            let index = match answer.parse::<usize>() {
+                Ok(number) => number.checked_sub(1),
+                Err(_) => {
+                    let matches = self
+                        .buffers
+                        .iter()
+                        .enumerate()
+                        .filter(|(_, buffer)| buffer.name().contains(answer))
+                        .map(|(index, _)| index)
+                        .collect::<Vec<_>>();
+
+                    match matches[..] {
+                        [index] => Some(index),
+                        _ => None,
+                    }
+                }
+            };
+
+            match index.filter(|index| *index < self.buffers.len()) {
+                Some(index) => self.switch_buffer(index),
+                None => {
+                    self.status_message =
+                        StatusMessage::from(format!("No single buffer matches: {answer}"))
+                }
+            }
+        }
+    }
+
+    fn convert_line_ending(&mut self) {
+        let answer = self
+            .prompt("Convert line endings to (lf/crlf/cr): ", |_, _, _| {})
+            .unwrap_or_default();
+
+        if let Some(answer) = answer {
+            match answer.parse::<LineEnding>() {
+                Ok(line_ending) => {
+                    self.document_mut().set_line_ending(line_ending);
+                    self.status_message =
+                        StatusMessage::from(format!("Line endings set to {line_ending}."));
+                }
+                Err(err) => self.status_message = StatusMessage::from(format!("{err}")),
+            }
+        }
+    }
+
+    fn undo(&mut self) {
+        self.clear_selection();
+
+        match self.document_mut().undo() {
+            Some(position) => self.cursor_position = position,
+            None => self.status_message = StatusMessage::from("Nothing to undo.".to_owned()),
+        }
+    }
+
+    fn redo(&mut self) {
+        self.clear_selection();
+
+        match self.document_mut().redo() {
+            Some(position) => self.cursor_position = position,
+            None => self.status_message = StatusMessage::from("Nothing to redo.".to_owned()),
         }
     }
 
     fn search(&mut self) {
         let old_position = self.cursor_position.clone();
         let mut direction = SearchDirection::Forward;
-        let query = self
-            .prompt(
-                "Search (ESC to cancel, Arrows to navigate): ",
-                |revise, key, query| {
+        let mut query = self.query.clone();
+
+        self.prompt_notice = query.modes();
+
+        let text = self
+            .prompt_recall(
+                PromptKind::Search,
+                "Search (ESC to cancel, Tab/Shift-Tab to navigate): ",
+                |revise, key, text| {
                     let mut moved = false;
 
                     match key {
-                        Key::Right | Key::Down => {
+                        Key::Char('\t') => {
                             direction = SearchDirection::Forward;
                             revise.move_cursor(Key::Right);
                             moved = true;
                         }
-                        Key::Left | Key::Up => direction = SearchDirection::Backward,
+                        Key::BackTab => direction = SearchDirection::Backward,
                         _ => direction = SearchDirection::Forward,
                     }
 
-                    if let Some(position) =
-                        revise
-                            .document
-                            .find(query, &revise.cursor_position, direction)
-                    {
+                    let Some(pattern) = revise.compile_query(&mut query, key, text) else {
+                        return;
+                    };
+
+                    if let Some((position, _, wrapped)) = revise.document().find_wrapping(
+                        &pattern,
+                        &revise.cursor_position,
+                        direction,
+                    ) {
+                        if wrapped {
+                            revise.prompt_notice.push_str(" search wrapped");
+                        }
+
                         revise.cursor_position = position;
                         revise.scroll();
                     } else if moved {
                         revise.move_cursor(Key::Left);
                     }
-
-                    revise.highlighted_word = Some(query.to_owned());
                 },
             )
             .unwrap_or_default();
 
-        if query.is_none() {
+        if text.is_none() {
             self.cursor_position = old_position;
             self.scroll();
         }
 
+        self.query = query;
+        self.highlighted_word = None;
+    }
+
+    /// Applies the mode toggles of a search prompt to `query` and compiles
+    /// it, showing the enabled modes or the pattern error next to the input.
+    fn compile_query(&mut self, query: &mut Query, key: Key, text: &str) -> Option<Pattern> {
+        match key {
+            Key::Alt('r') => query.regex = !query.regex,
+            Key::Alt('c') => query.ignore_case = !query.ignore_case,
+            Key::Alt('w') => query.whole_word = !query.whole_word,
+            _ => (),
+        }
+
+        text.clone_into(&mut query.text);
+        self.prompt_notice = query.modes();
+        self.highlighted_word = None;
+
+        if query.text.is_empty() {
+            return None;
+        }
+
+        match query.compile() {
+            Ok(pattern) => {
+                self.highlighted_word = Some(pattern.clone());
+                Some(pattern)
+            }
+            Err(err) => {
+                self.prompt_notice =
+                    format!("{} error: {}", query.modes(), query::short_error(&err));
+                None
+            }
+        }
+    }
+
+    fn replace(&mut self) {
+        let mut query = self.query.clone();
+
+        self.prompt_notice = query.modes();
+
+        let text = self
+            .prompt_recall(PromptKind::Search, "Replace: ", |revise, key, text| {
+                revise.compile_query(&mut query, key, text);
+            })
+            .unwrap_or_default();
+
+        self.query = query.clone();
+        self.highlighted_word = None;
+
+        if text.is_none() {
+            return;
+        }
+
+        let pattern = match query.compile() {
+            Ok(pattern) => pattern,
+            Err(err) => {
+                self.status_message =
+                    StatusMessage::from(format!("Invalid pattern: {}", query::short_error(&err)));
+                return;
+            }
+        };
+
+        if query.regex {
+            self.prompt_notice = "($1 or ${name} inserts a capture group)".to_owned();
+        }
+
+        let Some(replacement) = self
+            .prompt_line(
+                &format!("Replace {} with: ", query.text),
+                Some(PromptKind::Replace),
+                |_, _, _| {},
+            )
+            .unwrap_or_default()
+        else {
+            return;
+        };
+        let mut at = self.cursor_position.clone();
+        let mut replaced = 0;
+        // Counted once up front, then kept in step as matches are replaced
+        // or skipped.
+        let matches = self.document().find_all(&pattern);
+        let mut total = matches.len();
+        let mut index = matches
+            .iter()
+            .take_while(|(start, _)| (start.y, start.x) < (at.y, at.x))
+            .count();
+
+        self.highlighted_word = Some(pattern.clone());
+
+        while let Some((start, _)) = self
+            .document()
+            .find(&pattern, &at, SearchDirection::Forward)
+        {
+            self.cursor_position = start.clone();
+            self.scroll();
+            self.status_message = StatusMessage::from(format!(
+                "Replace? (y)es, (n)o, (a)ll, (q)uit - match {} of {}",
+                index.min(total.saturating_sub(1)) + 1,
+                total.max(1)
+            ));
+
+            if self.refresh_screen().is_err() {
+                break;
+            }
+
+            match self.read_key() {
+                Ok(Key::Char('y')) => {
+                    at = self.document_mut().replace(&start, &pattern, &replacement);
+                    replaced += 1;
+                    total = total.saturating_sub(1);
+                }
+                Ok(Key::Char('n')) => {
+                    index += 1;
+                    at = Position {
+                        x: start.x.saturating_add(1),
+                        y: start.y,
+                    };
+                }
+                Ok(Key::Char('a')) => {
+                    replaced += self
+                        .document_mut()
+                        .replace_all(&start, &pattern, &replacement);
+                    break;
+                }
+                Ok(Key::Char('q') | Key::Esc | Key::Ctrl('g')) | Err(_) => break,
+                Ok(_) => (),
+            }
+        }
+
         self.highlighted_word = None;
+        self.clear_selection();
+        self.status_message = StatusMessage::from(match replaced {
+            1 => "Replaced 1 occurrence.".to_owned(),
+            _ => format!("Replaced {replaced} occurrences."),
+        });
+    }
+
+    /// Returns the selected range in document order, unless it is empty.
+    fn selection(&self) -> Option<(Position, Position)> {
+        let anchor = self.anchor.as_ref()?;
+        let cursor = &self.cursor_position;
+
+        match (anchor.y, anchor.x).cmp(&(cursor.y, cursor.x)) {
+            Ordering::Less => Some((anchor.clone(), cursor.clone())),
+            Ordering::Greater => Some((cursor.clone(), anchor.clone())),
+            Ordering::Equal => None,
+        }
+    }
+
+    /// Columns of row `y` covered by the selection.
+    fn selection_in_row(&self, y: usize) -> Range<usize> {
+        match self.selection() {
+            Some((start, end)) if start.y <= y && y <= end.y => {
+                let from = if y == start.y { start.x } else { 0 };
+                let to = if y == end.y { end.x } else { usize::MAX };
+
+                from..to
+            }
+            _ => 0..0,
+        }
+    }
+
+    /// Extends the selection from where the cursor was before moving it.
+    fn select(&mut self, key: Key) {
+        if self.anchor.is_none() {
+            self.anchor = Some(self.cursor_position.clone());
+        }
+
+        self.document_mut().seal_history();
+        self.move_cursor(key);
+    }
+
+    /// Starts selecting from the cursor without holding Shift, or stops.
+    fn toggle_mark(&mut self) {
+        if self.marking {
+            self.clear_selection();
+            self.status_message = StatusMessage::from("Mark unset.".to_owned());
+        } else {
+            self.anchor = Some(self.cursor_position.clone());
+            self.marking = true;
+            self.status_message = StatusMessage::from("Mark set.".to_owned());
+        }
+    }
+
+    fn clear_selection(&mut self) {
+        self.anchor = None;
+        self.marking = false;
+    }
+
+    fn delete_selection(&mut self) {
+        if let Some((start, end)) = self.selection() {
+            self.document_mut().delete_range(&start, &end);
+            self.cursor_position = start;
+        }
+
+        self.clear_selection();
     }
 
     fn copy_content(&mut self) -> Result<(), Box<dyn Err>> {
-        let row = self.document.row(self.cursor_position.y);
+        if let Some((start, end)) = self.selection() {
+            let text = self.document().text(&start, &end);
+
+            return self.clipboard.set_contents(text);
+        }
+
+        let row = self.document().row(self.cursor_position.y);
 
         match row {
             Some(v) => self.clipboard.set_contents(v.as_string().to_owned()),
@@ -536,19 +2365,150 @@ impl Revise {
         }
     }
 
+    fn cut_content(&mut self) -> Result<(), Box<dyn Err>> {
+        if self.selection().is_none() {
+            return Err(Box::new(CutError));
+        }
+
+        self.copy_content()?;
+        self.delete_selection();
+
+        Ok(())
+    }
+
     fn paste_content(&mut self) -> Result<String, Box<dyn Err>> {
-        let content = self.clipboard.get_contents();
+        self.clipboard.get_contents()
+    }
 
-        match content {
-            Ok(mut v) => {
-                if v.is_empty() {
-                    v = String::from(" ");
-                    self.cursor_position.y = self.cursor_position.y.saturating_add(1);
-                }
+    /// Replaces the selection with `text` in one undo step and moves the
+    /// cursor after it.
+    fn paste(&mut self, text: &str) {
+        self.document_mut().begin_group();
+        self.delete_selection();
+        self.cursor_position = self.buffers[self.current]
+            .document
+            .insert_str(&self.cursor_position, text);
+        self.document_mut().end_group();
+    }
+
+    /// Kills the rest of the line, or the line break at its end.
+    fn kill_line(&mut self) {
+        let start = self.cursor_position.clone();
+        let len = self.document().row(start.y).map_or(0, Row::len);
+        let end = if start.x < len {
+            Position { x: len, y: start.y }
+        } else if start.y + 1 < self.document().len() {
+            Position {
+                x: 0,
+                y: start.y + 1,
+            }
+        } else {
+            return;
+        };
+
+        self.kill(&start, &end);
+    }
+
+    fn kill_region(&mut self) {
+        match self.selection() {
+            Some((start, end)) => self.kill(&start, &end),
+            None => {
+                self.status_message = StatusMessage::from(
+                    "The mark is not set now, so there is no region".to_owned(),
+                );
+            }
+        }
+    }
+
+    /// Moves the text between `start` and `end` to the kill ring, adding it
+    /// to the previous kill if the last command was one.
+    fn kill(&mut self, start: &Position, end: &Position) {
+        let text = self.document().text(start, end);
+        let append = matches!(
+            self.last_command,
+            Some(Command::KillLine | Command::KillRegion)
+        );
 
-                Ok(v)
+        self.kill_ring.kill(&text, append);
+        self.document_mut().delete_range(start, end);
+        self.cursor_position = start.clone();
+        self.clear_selection();
+    }
+
+    fn copy_region(&mut self) {
+        match self.selection() {
+            Some((start, end)) => {
+                let text = self.document().text(&start, &end);
+
+                self.kill_ring.kill(&text, false);
+                self.clear_selection();
+            }
+            None => {
+                self.status_message = StatusMessage::from(
+                    "The mark is not set now, so there is no region".to_owned(),
+                );
             }
-            Err(err) => Err(err),
         }
     }
+
+    fn yank(&mut self) {
+        let Some(text) = self.kill_ring.yank().map(str::to_owned) else {
+            self.status_message = StatusMessage::from("Kill ring is empty.".to_owned());
+            return;
+        };
+        let start = self.cursor_position.clone();
+
+        self.clear_selection();
+        self.document_mut().seal_history();
+        self.cursor_position = self.buffers[self.current]
+            .document
+            .insert_str(&start, &text);
+        self.yanked = Some((start, self.cursor_position.clone()));
+    }
+
+    /// Replaces the text just yanked with the previous kill ring entry.
+    fn yank_pop(&mut self) {
+        let yanked = self.yanked.take();
+        let (Some(Command::Yank | Command::YankPop), Some((start, end))) =
+            (self.last_command, yanked)
+        else {
+            self.status_message =
+                StatusMessage::from("Previous command was not a yank.".to_owned());
+            return;
+        };
+        let Some(text) = self.kill_ring.rotate().map(str::to_owned) else {
+            return;
+        };
+
+        self.document_mut().begin_group();
+        self.document_mut().delete_range(&start, &end);
+        self.cursor_position = self.buffers[self.current]
+            .document
+            .insert_str(&start, &text);
+        self.document_mut().end_group();
+        self.yanked = Some((start, self.cursor_position.clone()));
+    }
+}
+
+/// Index of the visual line starting at one of `starts` that contains `x`.
+fn segment_of(starts: &[usize], x: usize) -> usize {
+    starts
+        .partition_point(|start| *start <= x)
+        .saturating_sub(1)
+}
+
+/// Lists the keys bound to the most used commands.
+fn help(keymap: &Keymap) -> String {
+    let commands = [
+        (Command::Search, "find"),
+        (Command::Save, "save"),
+        (Command::Undo, "undo"),
+        (Command::Quit, "quit"),
+    ];
+    let keys: Vec<String> = commands
+        .iter()
+        .filter_map(|&(command, what)| Some(format!("{} = {what}", keymap.binding(command)?)))
+        .collect();
+
+    format!("HELP: {}", keys.join(" | "))
 }
@@ -0,0 +1,107 @@
+use crate::swap::Swap;
+use crate::Document;
+use crate::Position;
+
+use std::io::Error as IOError;
+use std::path::PathBuf;
+
+/// How lines wider than the pane are shown.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Wrap {
+    /// Scroll horizontally.
+    #[default]
+    Off,
+    /// Continue on the next screen line at the pane edge.
+    Anywhere,
+    /// Continue on the next screen line after the last whitespace that fits.
+    Words,
+}
+
+/// A document opened in the editor, along with where the user left it.
+#[derive(Default)]
+pub struct Buffer {
+    pub document: Document,
+    pub cursor_position: Position,
+    /// First row shown and, when wrapping, its first visual line shown as `x`.
+    pub offset: Position,
+    pub wrap: Wrap,
+    pub pending_swap: Option<Swap>,
+    /// A swap file left by an earlier session that the user chose to keep.
+    pub kept_swap: Option<PathBuf>,
+    swap_path: Option<PathBuf>,
+    swapped_version: u64,
+}
+
+impl Buffer {
+    pub fn new(document: Document) -> Self {
+        let wrap = if document.soft_wrap() {
+            Wrap::Words
+        } else {
+            Wrap::Off
+        };
+
+        Self {
+            document,
+            wrap,
+            ..Self::default()
+        }
+    }
+
+    pub fn name(&self) -> String {
+        match &self.document.filename {
+            Some(name) => name.clone(),
+            None => "[No Name]".to_owned(),
+        }
+    }
+
+    /// Looks for a swap file left behind by a previous session and returns a
+    /// warning if it belongs to an editor that is still running.
+    pub fn check_swap(&mut self) -> Option<String> {
+        let path = self.document.filename.as_deref().and_then(Swap::find)?;
+
+        match Swap::read(&path) {
+            Ok(swap) if swap.is_stale() => {
+                self.pending_swap = Some(swap);
+                None
+            }
+            Ok(swap) => Some(format!(
+                "WARNING: {} is being edited by process {}",
+                swap.filename, swap.pid
+            )),
+            Err(err) => Some(format!("ERR: {}: {err}", path.display())),
+        }
+    }
+
+    /// Journals unsaved changes to the swap file, or drops it once there are none.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Error` if the swap file cannot be written
+    pub fn update_swap(&mut self) -> Result<(), IOError> {
+        if !self.document.is_changed() {
+            self.remove_swap();
+            return Ok(());
+        }
+
+        if self.document.version() == self.swapped_version {
+            return Ok(());
+        }
+
+        let path = Swap::write(&self.document, self.kept_swap.as_deref())?;
+
+        if path != self.swap_path {
+            self.remove_swap();
+        }
+
+        self.swap_path = path;
+        self.swapped_version = self.document.version();
+
+        Ok(())
+    }
+
+    pub fn remove_swap(&mut self) {
+        if let Some(path) = self.swap_path.take() {
+            Swap::remove(&path);
+        }
+    }
+}
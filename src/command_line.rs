@@ -0,0 +1,344 @@
+use crate::application::LineNumbers;
+use crate::buffer::Wrap;
+use crate::config::MAX_TAB_WIDTH;
+use crate::goto::Target;
+use crate::keymap::Command;
+use crate::FileType;
+
+use std::fs;
+use thiserror::Error;
+
+/// Commands that take an argument, besides the key binding commands.
+const NAMES: [&str; 4] = ["edit", "filetype", "set", "write"];
+
+/// Short names accepted for some commands.
+const ALIASES: [(&str, &str); 4] = [
+    ("e", "edit"),
+    ("ft", "filetype"),
+    ("q", "quit"),
+    ("w", "write"),
+];
+
+/// Options `set` accepts, as completed.
+const OPTIONS: [&str; 11] = [
+    "expandtabs",
+    "linenumbers=absolute",
+    "linenumbers=off",
+    "linenumbers=relative",
+    "noexpandtabs",
+    "novi",
+    "tabwidth=",
+    "vi",
+    "wrap=anywhere",
+    "wrap=off",
+    "wrap=words",
+];
+
+/// A line typed in the command line, e.g. `w notes.md` or `set tabwidth=2`.
+#[derive(Debug, PartialEq)]
+pub enum Invocation {
+    Run(Command),
+    /// Saves the document under a new name.
+    Write(String),
+    /// Opens a file in a new buffer.
+    Edit(String),
+    /// Moves to a line, or a column of it.
+    Goto(Target),
+    Set(Setting),
+    FileType(String),
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Setting {
+    TabWidth(usize),
+    ExpandTabs(bool),
+    Vi(bool),
+    LineNumbers(LineNumbers),
+    Wrap(Wrap),
+}
+
+#[derive(Debug, Error, PartialEq)]
+pub enum CommandLineError {
+    #[error("unknown command `{0}`")]
+    UnknownCommand(String),
+    #[error("{0} needs {1}")]
+    MissingArgument(&'static str, &'static str),
+    #[error("{0} takes no argument")]
+    UnexpectedArgument(String),
+    #[error("unknown option `{0}`")]
+    UnknownOption(String),
+    #[error("invalid value `{1}` for {0}")]
+    InvalidValue(&'static str, String),
+    #[error("unknown file type `{0}`")]
+    UnknownFileType(String),
+}
+
+/// Parses a command line such as `e src/main.rs`. A leading `:` is ignored.
+///
+/// # Errors
+///
+/// Will return `CommandLineError` if the command is unknown or its argument
+/// is missing or invalid
+pub fn parse(line: &str) -> Result<Invocation, CommandLineError> {
+    let line = line.trim().trim_start_matches(':');
+    let (name, argument) = match line.split_once(char::is_whitespace) {
+        Some((name, argument)) => (name, argument.trim()),
+        None => (line, ""),
+    };
+
+    match (full_name(name), argument) {
+        ("write", "") => Ok(Invocation::Run(Command::Save)),
+        ("write", filename) => Ok(Invocation::Write(filename.to_owned())),
+        ("edit", "") => Err(CommandLineError::MissingArgument("edit", "a file name")),
+        ("edit", filename) => Ok(Invocation::Edit(filename.to_owned())),
+        ("goto", target) if !target.is_empty() => match Target::parse(target) {
+            Some(target) => Ok(Invocation::Goto(target)),
+            None => Err(CommandLineError::InvalidValue("goto", target.to_owned())),
+        },
+        ("set", "") => Err(CommandLineError::MissingArgument("set", "an option")),
+        ("set", option) => parse_setting(option).map(Invocation::Set),
+        ("filetype", "") => Err(CommandLineError::MissingArgument(
+            "filetype",
+            "a file type name",
+        )),
+        ("filetype", name) => match FileType::named(name) {
+            Some(_) => Ok(Invocation::FileType(name.to_owned())),
+            None => Err(CommandLineError::UnknownFileType(name.to_owned())),
+        },
+        (name, argument) => match Command::from_name(name) {
+            Some(command) if argument.is_empty() => Ok(Invocation::Run(command)),
+            Some(_) => Err(CommandLineError::UnexpectedArgument(name.to_owned())),
+            None => Err(CommandLineError::UnknownCommand(name.to_owned())),
+        },
+    }
+}
+
+fn full_name(name: &str) -> &str {
+    ALIASES
+        .iter()
+        .find(|(alias, _)| *alias == name)
+        .map_or(name, |(_, full)| full)
+}
+
+fn parse_setting(option: &str) -> Result<Setting, CommandLineError> {
+    let (name, value) = option.split_once('=').unwrap_or((option, ""));
+    let invalid = |name| CommandLineError::InvalidValue(name, value.to_owned());
+
+    match (name, value) {
+        ("tabwidth", value) => match value.parse() {
+            Ok(width) if (1..=MAX_TAB_WIDTH).contains(&width) => Ok(Setting::TabWidth(width)),
+            _ => Err(invalid("tabwidth")),
+        },
+        ("expandtabs", "") => Ok(Setting::ExpandTabs(true)),
+        ("noexpandtabs", "") => Ok(Setting::ExpandTabs(false)),
+        ("vi", "") => Ok(Setting::Vi(true)),
+        ("novi", "") => Ok(Setting::Vi(false)),
+        ("linenumbers", value) => match value {
+            "off" => Ok(Setting::LineNumbers(LineNumbers::Off)),
+            "absolute" => Ok(Setting::LineNumbers(LineNumbers::Absolute)),
+            "relative" => Ok(Setting::LineNumbers(LineNumbers::Relative)),
+            _ => Err(invalid("linenumbers")),
+        },
+        ("wrap", value) => match value {
+            "off" => Ok(Setting::Wrap(Wrap::Off)),
+            "anywhere" => Ok(Setting::Wrap(Wrap::Anywhere)),
+            "words" => Ok(Setting::Wrap(Wrap::Words)),
+            _ => Err(invalid("wrap")),
+        },
+        _ => Err(CommandLineError::UnknownOption(option.to_owned())),
+    }
+}
+
+/// Lists the ways to finish `line`, each as the whole completed line, in
+/// order.
+pub fn complete(line: &str) -> Vec<String> {
+    let Some((name, argument)) = line.split_once(' ') else {
+        let mut names = NAMES
+            .into_iter()
+            .chain(Command::ALL.iter().map(|command| command.name()))
+            .filter(|name| name.starts_with(line))
+            .map(str::to_owned)
+            .collect::<Vec<_>>();
+
+        names.sort();
+        return names;
+    };
+    let argument = argument.trim_start();
+    let head = &line[..line.len() - argument.len()];
+    let candidates = match full_name(name) {
+        "write" | "edit" => complete_path(argument),
+        "set" => starting_with(&OPTIONS, argument),
+        "filetype" => starting_with(FileType::names(), argument),
+        _ => Vec::new(),
+    };
+
+    candidates
+        .into_iter()
+        .map(|candidate| format!("{head}{candidate}"))
+        .collect()
+}
+
+/// Lists the files and directories whose path starts with `partial`, in
+/// order. Directories end with `/`, and hidden files are only listed when
+/// `partial` names one.
+pub fn complete_path(partial: &str) -> Vec<String> {
+    let (dir, prefix) = match partial.rfind('/') {
+        Some(slash) => partial.split_at(slash + 1),
+        None => ("", partial),
+    };
+    let Ok(entries) = fs::read_dir(if dir.is_empty() { "." } else { dir }) else {
+        return Vec::new();
+    };
+    let mut paths = entries
+        .filter_map(|entry| {
+            let entry = entry.ok()?;
+            let name = entry.file_name().into_string().ok()?;
+
+            if !name.starts_with(prefix) || (name.starts_with('.') && !prefix.starts_with('.')) {
+                return None;
+            }
+
+            let slash = if entry.path().is_dir() { "/" } else { "" };
+
+            Some(format!("{dir}{name}{slash}"))
+        })
+        .collect::<Vec<_>>();
+
+    paths.sort();
+    paths
+}
+
+/// The longest text every candidate starts with.
+pub fn common_prefix(candidates: &[String]) -> &str {
+    let Some((first, rest)) = candidates.split_first() else {
+        return "";
+    };
+    let len = rest.iter().fold(first.len(), |len, candidate| {
+        first[..len]
+            .char_indices()
+            .zip(candidate.chars())
+            .find(|((_, a), b)| a != b)
+            .map_or(len.min(candidate.len()), |((index, _), _)| index)
+    });
+
+    &first[..len]
+}
+
+fn starting_with(words: &[&str], prefix: &str) -> Vec<String> {
+    words
+        .iter()
+        .filter(|word| word.to_lowercase().starts_with(&prefix.to_lowercase()))
+        .map(|word| (*word).to_owned())
+        .collect()
+}
+
+#[cfg(test)]
+mod command_line_tests {
+    use super::*;
+    use std::env;
+    use std::process;
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(parse("w"), Ok(Invocation::Run(Command::Save)));
+        assert_eq!(
+            parse(":w  notes.md "),
+            Ok(Invocation::Write("notes.md".to_owned()))
+        );
+        assert_eq!(
+            parse("e src/main.rs"),
+            Ok(Invocation::Edit("src/main.rs".to_owned()))
+        );
+        assert_eq!(
+            parse("goto 120:8"),
+            Ok(Invocation::Goto(Target::parse("120:8").unwrap()))
+        );
+        assert_eq!(parse("goto"), Ok(Invocation::Run(Command::Goto)));
+        assert_eq!(
+            parse("set tabwidth=4"),
+            Ok(Invocation::Set(Setting::TabWidth(4)))
+        );
+        assert_eq!(
+            parse("set wrap=anywhere"),
+            Ok(Invocation::Set(Setting::Wrap(Wrap::Anywhere)))
+        );
+        assert_eq!(
+            parse("filetype rust"),
+            Ok(Invocation::FileType("rust".to_owned()))
+        );
+        assert_eq!(parse("q"), Ok(Invocation::Run(Command::Quit)));
+        assert_eq!(
+            parse("toggle-wrap"),
+            Ok(Invocation::Run(Command::ToggleWrap))
+        );
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let error = |line| parse(line).unwrap_err().to_string();
+
+        assert_eq!(error("frobnicate"), "unknown command `frobnicate`");
+        assert_eq!(error("e"), "edit needs a file name");
+        assert_eq!(error("goto 0"), "invalid value `0` for goto");
+        assert_eq!(error("set tabwidth=40"), "invalid value `40` for tabwidth");
+        assert_eq!(error("set colour"), "unknown option `colour`");
+        assert_eq!(error("filetype cobol"), "unknown file type `cobol`");
+        assert_eq!(error("undo twice"), "undo takes no argument");
+    }
+
+    #[test]
+    fn test_complete_commands_and_options() {
+        assert_eq!(complete("go"), ["goto"]);
+        assert_eq!(complete("sp"), ["split-side-by-side", "split-stacked"]);
+        assert_eq!(
+            complete("set line"),
+            [
+                "set linenumbers=absolute",
+                "set linenumbers=off",
+                "set linenumbers=relative"
+            ]
+        );
+        assert_eq!(complete("ft ru"), ["ft Rust"]);
+        assert!(complete("undo x").is_empty());
+        assert_eq!(common_prefix(&complete("set line")), "set linenumbers=");
+        assert_eq!(common_prefix(&complete("sp")), "split-s");
+        assert_eq!(common_prefix(&[]), "");
+    }
+
+    #[test]
+    fn test_complete_path() {
+        let dir = env::temp_dir().join(format!("revise-complete-path-{}", process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("src")).unwrap();
+        fs::write(dir.join("notes.md"), "").unwrap();
+        fs::write(dir.join("notes.txt"), "").unwrap();
+        fs::write(dir.join(".hidden"), "").unwrap();
+
+        let dir_name = format!("{}/", dir.display());
+
+        assert_eq!(
+            complete_path(&format!("{dir_name}no")),
+            [
+                format!("{dir_name}notes.md"),
+                format!("{dir_name}notes.txt")
+            ]
+        );
+        assert_eq!(
+            complete_path(&dir_name),
+            [
+                format!("{dir_name}notes.md"),
+                format!("{dir_name}notes.txt"),
+                format!("{dir_name}src/")
+            ]
+        );
+        assert_eq!(
+            complete_path(&format!("{dir_name}.h")),
+            [format!("{dir_name}.hidden")]
+        );
+        assert_eq!(
+            complete(&format!("e {dir_name}s")),
+            [format!("e {dir_name}src/")]
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
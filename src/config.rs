@@ -0,0 +1,273 @@
+use crate::application::LineNumbers;
+use crate::keymap::Preset;
+
+use serde::{Deserialize, Deserializer};
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::io::Error as IOError;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use termion::color::Rgb;
+use thiserror::Error;
+
+pub const MAX_TAB_WIDTH: usize = 16;
+
+/// Settings read from `$XDG_CONFIG_HOME/revise/config.toml` (default
+/// `~/.config/revise/config.toml`). Every key is optional.
+#[derive(Debug, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub tab_width: usize,
+    /// Insert spaces up to the next tab stop when Tab is pressed.
+    pub expand_tabs: bool,
+    /// Extra Ctrl-Q presses needed to quit with unsaved changes.
+    pub quit_times: u8,
+    /// Seconds a status message stays on screen.
+    pub message_timeout: u64,
+    pub line_numbers: LineNumbers,
+    /// Start in vi-like modal editing.
+    pub vi_mode: bool,
+    pub colors: Theme,
+    /// Bindings the `keys` table starts from, `default` or `emacs`.
+    pub keymap: Preset,
+    /// Keys bound to commands on top of the defaults, e.g.
+    /// `"Ctrl-X Ctrl-S" = "save"`.
+    pub keys: BTreeMap<String, String>,
+}
+
+/// Colors of the text and of the editor around it, written `"#rrggbb"`.
+#[derive(Debug, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Theme {
+    #[serde(deserialize_with = "rgb")]
+    pub text: Rgb,
+    #[serde(deserialize_with = "rgb")]
+    pub number: Rgb,
+    #[serde(deserialize_with = "rgb")]
+    pub string: Rgb,
+    #[serde(deserialize_with = "rgb")]
+    pub char: Rgb,
+    #[serde(deserialize_with = "rgb")]
+    pub comment: Rgb,
+    #[serde(deserialize_with = "rgb")]
+    pub primary_keyword: Rgb,
+    #[serde(deserialize_with = "rgb")]
+    pub secondary_keyword: Rgb,
+    #[serde(deserialize_with = "rgb", rename = "match")]
+    pub search_match: Rgb,
+    #[serde(deserialize_with = "rgb")]
+    pub selection: Rgb,
+    #[serde(deserialize_with = "rgb")]
+    pub gutter: Rgb,
+    #[serde(deserialize_with = "rgb")]
+    pub status_fg: Rgb,
+    #[serde(deserialize_with = "rgb")]
+    pub status_bg: Rgb,
+    #[serde(deserialize_with = "rgb")]
+    pub inactive_status_bg: Rgb,
+}
+
+#[derive(Debug, Error)]
+pub enum ConfigError {
+    #[error("{0}: {1}")]
+    Read(String, IOError),
+    #[error("{0}:{1}: {2}")]
+    Invalid(String, usize, String),
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            tab_width: 4,
+            expand_tabs: false,
+            quit_times: 1,
+            message_timeout: 5,
+            line_numbers: LineNumbers::Off,
+            vi_mode: false,
+            colors: Theme::default(),
+            keymap: Preset::Default,
+            keys: BTreeMap::new(),
+        }
+    }
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Self {
+            text: Rgb(255, 255, 255),
+            number: Rgb(220, 163, 163),
+            string: Rgb(211, 54, 130),
+            char: Rgb(108, 113, 196),
+            comment: Rgb(133, 153, 0),
+            primary_keyword: Rgb(181, 137, 0),
+            secondary_keyword: Rgb(42, 161, 152),
+            search_match: Rgb(30, 139, 210),
+            selection: Rgb(68, 85, 120),
+            gutter: Rgb(120, 120, 120),
+            status_fg: Rgb(63, 63, 63),
+            status_bg: Rgb(239, 239, 239),
+            inactive_status_bg: Rgb(160, 160, 160),
+        }
+    }
+}
+
+impl Config {
+    /// Reads the config file at `path`, or at the default location if `path`
+    /// is `None`. A missing default file gives the default settings.
+    ///
+    /// # Errors
+    ///
+    /// Will return `ConfigError` if the file cannot be read or has an
+    /// unknown key or an invalid value
+    pub fn load(path: Option<&str>) -> Result<Self, ConfigError> {
+        let path = match path {
+            Some(path) => PathBuf::from(path),
+            None => match default_path() {
+                Some(path) if path.exists() => path,
+                _ => return Ok(Self::default()),
+            },
+        };
+
+        match fs::read_to_string(&path) {
+            Ok(contents) => Self::parse(&contents, &path),
+            Err(err) => Err(ConfigError::Read(path.display().to_string(), err)),
+        }
+    }
+
+    fn parse(contents: &str, path: &Path) -> Result<Self, ConfigError> {
+        let invalid = |offset: usize, message: String| {
+            let line = contents[..offset.min(contents.len())].matches('\n').count() + 1;
+
+            ConfigError::Invalid(path.display().to_string(), line, message)
+        };
+        let config: Self = toml::from_str(contents).map_err(|err| {
+            let offset = err.span().map_or(0, |span| span.start);
+
+            invalid(offset, err.message().to_owned())
+        })?;
+
+        if !(1..=MAX_TAB_WIDTH).contains(&config.tab_width) {
+            let offset = contents.find("tab_width").unwrap_or(0);
+
+            return Err(invalid(
+                offset,
+                format!("tab_width must be between 1 and {MAX_TAB_WIDTH}"),
+            ));
+        }
+
+        if config.message_timeout == 0 {
+            let offset = contents.find("message_timeout").unwrap_or(0);
+
+            return Err(invalid(
+                offset,
+                "message_timeout must be at least 1 second".to_owned(),
+            ));
+        }
+
+        Ok(config)
+    }
+
+    pub fn message_timeout(&self) -> Duration {
+        Duration::from_secs(self.message_timeout)
+    }
+}
+
+/// Returns `$XDG_CONFIG_HOME/revise/config.toml`, falling back to
+/// `~/.config/revise/config.toml`.
+pub fn default_path() -> Option<PathBuf> {
+    let base = match env::var_os("XDG_CONFIG_HOME").filter(|dir| !dir.is_empty()) {
+        Some(dir) => PathBuf::from(dir),
+        None => PathBuf::from(env::var_os("HOME")?).join(".config"),
+    };
+
+    Some(base.join("revise").join("config.toml"))
+}
+
+/// Where revise keeps state between sessions: `$XDG_STATE_HOME/revise`,
+/// falling back to `~/.local/state/revise`.
+pub fn state_dir() -> Option<PathBuf> {
+    let base = match env::var_os("XDG_STATE_HOME").filter(|dir| !dir.is_empty()) {
+        Some(dir) => PathBuf::from(dir),
+        None => PathBuf::from(env::var_os("HOME")?).join(".local/state"),
+    };
+
+    Some(base.join("revise"))
+}
+
+fn rgb<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Rgb, D::Error> {
+    let hex = String::deserialize(deserializer)?;
+
+    parse_rgb(&hex).ok_or_else(|| {
+        serde::de::Error::custom(format!("invalid color `{hex}`, expected `#rrggbb`"))
+    })
+}
+
+fn parse_rgb(hex: &str) -> Option<Rgb> {
+    let digits = hex.strip_prefix('#').filter(|digits| digits.len() == 6)?;
+    let channel = |at: usize| u8::from_str_radix(digits.get(at..at + 2)?, 16).ok();
+
+    Some(Rgb(channel(0)?, channel(2)?, channel(4)?))
+}
+
+#[cfg(test)]
+mod config_tests {
+    use super::*;
+
+    fn parse(contents: &str) -> Result<Config, ConfigError> {
+        Config::parse(contents, Path::new("config.toml"))
+    }
+
+    #[test]
+    fn test_defaults_when_empty() {
+        assert_eq!(parse("").unwrap(), Config::default());
+    }
+
+    #[test]
+    fn test_parse_settings() {
+        let config = parse(
+            "tab_width = 8\nexpand_tabs = true\nline_numbers = \"relative\"\nvi_mode = true\n\n\
+             [colors]\nstatus_bg = \"#102030\"\nmatch = \"#FFffFF\"\n",
+        )
+        .unwrap();
+
+        assert_eq!(config.tab_width, 8);
+        assert!(config.expand_tabs);
+        assert_eq!(config.line_numbers, LineNumbers::Relative);
+        assert!(config.vi_mode);
+        assert_eq!(config.colors.status_bg, Rgb(16, 32, 48));
+        assert_eq!(config.colors.search_match, Rgb(255, 255, 255));
+        assert_eq!(config.colors.text, Theme::default().text);
+        assert_eq!(config.quit_times, 1);
+        assert!(config.keys.is_empty());
+    }
+
+    #[test]
+    fn test_parse_keys() {
+        let config = parse(
+            "keymap = \"emacs\"\n[keys]\n\"Ctrl-X Ctrl-S\" = \"save\"\n\"Ctrl-S\" = \"none\"\n",
+        )
+        .unwrap();
+
+        assert_eq!(config.keymap, Preset::Emacs);
+        assert_eq!(config.keys["Ctrl-X Ctrl-S"], "save");
+        assert_eq!(config.keys["Ctrl-S"], "none");
+    }
+
+    #[test]
+    fn test_report_bad_keys_and_values() {
+        let error = |contents| parse(contents).unwrap_err().to_string();
+
+        assert!(error("tab_width = 4\ntabwidth = 4")
+            .starts_with("config.toml:2: unknown field `tabwidth`"));
+        assert_eq!(
+            error("[colors]\ngutter = \"grey\""),
+            "config.toml:2: invalid color `grey`, expected `#rrggbb`"
+        );
+        assert_eq!(
+            error("quit_times = 2\ntab_width = 0"),
+            "config.toml:2: tab_width must be between 1 and 16"
+        );
+        assert!(error("quit_times = -1").starts_with("config.toml:1: "));
+    }
+}
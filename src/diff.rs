@@ -0,0 +1,193 @@
+/// Above this many cells in the table of common lines, the lines between the
+/// common start and end are reported as one change instead.
+const MAX_TABLE_SIZE: usize = 4_000_000;
+/// How many hunks `describe` lists before summing up the rest.
+const MAX_DESCRIBED: usize = 4;
+
+/// A run of lines that differs between two texts.
+#[derive(Debug, PartialEq)]
+pub struct Hunk {
+    /// Line of the old text the hunk starts at, counted from 1.
+    pub line: usize,
+    /// Lines of the old text replaced.
+    pub removed: usize,
+    /// Lines of the new text replacing them.
+    pub added: usize,
+}
+
+/// Finds the hunks turning `old` into `new`, keeping the longest run of
+/// common lines.
+pub fn diff(old: &[&str], new: &[&str]) -> Vec<Hunk> {
+    let prefix = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let old_middle = &old[prefix..old.len() - suffix];
+    let new_middle = &new[prefix..new.len() - suffix];
+
+    if old_middle.is_empty() && new_middle.is_empty() {
+        return Vec::new();
+    }
+
+    let cells = (old_middle.len() + 1).saturating_mul(new_middle.len() + 1);
+
+    if cells > MAX_TABLE_SIZE {
+        return vec![Hunk {
+            line: prefix + 1,
+            removed: old_middle.len(),
+            added: new_middle.len(),
+        }];
+    }
+
+    let mut hunks = Vec::new();
+    let mut current: Option<Hunk> = None;
+
+    for step in steps(old_middle, new_middle) {
+        let (line, removed) = match step {
+            Step::Same => {
+                hunks.extend(current.take());
+                continue;
+            }
+            Step::Removed(line) => (line, 1),
+            Step::Added(line) => (line, 0),
+        };
+        let hunk = current.get_or_insert(Hunk {
+            line: prefix + line + 1,
+            removed: 0,
+            added: 0,
+        });
+
+        hunk.removed += removed;
+        hunk.added += 1 - removed;
+    }
+
+    hunks.extend(current);
+    hunks
+}
+
+/// Describes `hunks` in a few words, like `line 2: 1 added, lines 5-6: 2
+/// changed to 3`.
+pub fn describe(hunks: &[Hunk]) -> String {
+    let mut parts: Vec<String> = hunks
+        .iter()
+        .take(MAX_DESCRIBED)
+        .map(|hunk| {
+            let lines = match hunk.removed {
+                0 | 1 => format!("line {}", hunk.line),
+                removed => format!("lines {}-{}", hunk.line, hunk.line + removed - 1),
+            };
+            let change = match (hunk.removed, hunk.added) {
+                (0, added) => format!("{added} added"),
+                (removed, 0) => format!("{removed} removed"),
+                (removed, added) if removed == added => format!("{removed} changed"),
+                (removed, added) => format!("{removed} changed to {added}"),
+            };
+
+            format!("{lines}: {change}")
+        })
+        .collect();
+
+    if hunks.len() > MAX_DESCRIBED {
+        parts.push(format!("{} more", hunks.len() - MAX_DESCRIBED));
+    }
+
+    parts.join(", ")
+}
+
+enum Step {
+    /// A line both texts have.
+    Same,
+    /// Index in the old text of a line only it has.
+    Removed(usize),
+    /// Index in the old text before which a line of the new text goes.
+    Added(usize),
+}
+
+/// Walks both texts along a longest common subsequence of their lines.
+fn steps(old: &[&str], new: &[&str]) -> Vec<Step> {
+    let width = new.len() + 1;
+    // `common[i * width + j]` is the length of the longest common
+    // subsequence of `old[i..]` and `new[j..]`.
+    let mut common = vec![0u32; (old.len() + 1) * width];
+
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            common[i * width + j] = if old[i] == new[j] {
+                common[(i + 1) * width + j + 1] + 1
+            } else {
+                common[(i + 1) * width + j].max(common[i * width + j + 1])
+            };
+        }
+    }
+
+    let (mut i, mut j) = (0, 0);
+    let mut steps = Vec::new();
+
+    while i < old.len() || j < new.len() {
+        if i < old.len() && j < new.len() && old[i] == new[j] {
+            steps.push(Step::Same);
+            i += 1;
+            j += 1;
+        } else if j < new.len()
+            && (i == old.len() || common[i * width + j + 1] >= common[(i + 1) * width + j])
+        {
+            steps.push(Step::Added(i));
+            j += 1;
+        } else {
+            steps.push(Step::Removed(i));
+            i += 1;
+        }
+    }
+
+    steps
+}
+
+#[cfg(test)]
+mod diff_tests {
+    use super::*;
+
+    fn hunk(line: usize, removed: usize, added: usize) -> Hunk {
+        Hunk {
+            line,
+            removed,
+            added,
+        }
+    }
+
+    #[test]
+    fn test_diff() {
+        let old = ["a", "b", "c", "d", "e"];
+
+        assert!(diff(&old, &old).is_empty());
+        assert_eq!(diff(&old, &["a", "x", "b", "c", "d", "e"]), [hunk(2, 0, 1)]);
+        assert_eq!(diff(&old, &["a", "b", "d", "e"]), [hunk(3, 1, 0)]);
+        assert_eq!(
+            diff(&old, &["a", "B", "c", "d", "E", "f"]),
+            [hunk(2, 1, 1), hunk(5, 1, 2)]
+        );
+        assert_eq!(diff(&[], &["a", "b"]), [hunk(1, 0, 2)]);
+        assert_eq!(diff(&old, &[]), [hunk(1, 5, 0)]);
+    }
+
+    #[test]
+    fn test_describe() {
+        assert_eq!(
+            describe(&[hunk(2, 0, 1), hunk(5, 2, 3), hunk(9, 1, 0), hunk(12, 2, 2)]),
+            "line 2: 1 added, lines 5-6: 2 changed to 3, line 9: 1 removed, lines 12-13: 2 changed"
+        );
+        assert_eq!(
+            describe(&[
+                hunk(1, 1, 1),
+                hunk(3, 1, 1),
+                hunk(5, 1, 1),
+                hunk(7, 1, 1),
+                hunk(9, 1, 1),
+                hunk(11, 1, 1)
+            ]),
+            "line 1: 1 changed, line 3: 1 changed, line 5: 1 changed, line 7: 1 changed, 2 more"
+        );
+    }
+}
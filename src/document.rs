@@ -1,20 +1,59 @@
+use crate::diff::{self, Hunk};
 use crate::highlight::HighlightError;
+use crate::history::{History, Operation};
+use crate::line_ending::LineEnding;
 use crate::row::RowError;
+use crate::swap::Swap;
 use crate::FileType;
+use crate::Pattern;
 use crate::Position;
+use crate::Rope;
 use crate::Row;
 use crate::SearchDirection;
 
 use std::error::Error;
-use std::fs::{read_to_string, File};
-use std::io::{Error as IOError, Write};
+use std::fs::{self, read_to_string, File};
+use std::io::{BufWriter, Error as IOError, Write};
+use std::os::unix::fs::{chown, MetadataExt};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+use unicode_segmentation::UnicodeSegmentation;
+
+/// What the file looked like on disk the last time it was read or written.
+#[derive(Clone, Copy, PartialEq)]
+struct DiskState {
+    modified: Option<SystemTime>,
+    len: u64,
+    device: u64,
+    inode: u64,
+}
 
 #[derive(Default)]
 pub struct Document {
-    rows: Vec<Row>,
+    rows: Rope,
     pub filename: Option<String>,
     changed: bool,
     file_type: FileType,
+    /// Whether `file_type` was chosen by the user rather than guessed from
+    /// the file name.
+    file_type_chosen: bool,
+    history: History,
+    highlighted_until: usize,
+    line_ending: LineEnding,
+    saved_line_ending: LineEnding,
+    /// Whether the file had several styles of line terminator when read.
+    /// Saving writes `line_ending` after every row.
+    mixed_line_endings: bool,
+    missing_newline: bool,
+    backup: bool,
+    version: u64,
+    disk_state: Option<DiskState>,
+    /// What the file looked like when the user chose to keep editing over a
+    /// change on disk, `Some(None)` if it was deleted.
+    ignored_disk_state: Option<Option<DiskState>>,
+    /// Rows added after a row (positive) or joined into it (negative) by the
+    /// edits since `take_row_shifts` was last called.
+    row_shifts: Vec<(usize, isize)>,
 }
 
 impl Document {
@@ -24,17 +63,27 @@ impl Document {
     pub fn open(filename: &str) -> Result<Self, IOError> {
         let contents = read_to_string(filename)?;
         let file_type = FileType::new().from(filename);
-        let mut rows = Vec::new();
-
-        for value in contents.lines() {
-            rows.push(Row::from(value));
-        }
+        let line_ending = LineEnding::detect(&contents);
+        let (lines, trailing_newline) = LineEnding::split(&contents);
+        let rows = lines.into_iter().map(Row::from).collect::<Vec<_>>();
 
         Ok(Self {
-            rows,
+            missing_newline: !rows.is_empty() && !trailing_newline,
+            rows: Rope::from(rows),
             filename: Some(filename.to_owned()),
             changed: false,
             file_type,
+            file_type_chosen: false,
+            history: History::default(),
+            highlighted_until: 0,
+            line_ending,
+            saved_line_ending: line_ending,
+            mixed_line_endings: LineEnding::is_mixed(&contents),
+            backup: false,
+            version: 0,
+            disk_state: DiskState::read(filename),
+            ignored_disk_state: None,
+            row_shifts: Vec::new(),
         })
     }
 
@@ -42,6 +91,10 @@ impl Document {
         self.rows.get(index)
     }
 
+    pub fn rows(&self) -> impl Iterator<Item = &Row> {
+        self.rows.iter()
+    }
+
     pub fn is_empty(&self) -> bool {
         self.rows.is_empty()
     }
@@ -50,12 +103,46 @@ impl Document {
         self.rows.len()
     }
 
+    /// The position after `at`, going through the end of each row.
+    pub fn next_position(&self, at: &Position) -> Option<Position> {
+        if at.x < self.row(at.y).map_or(0, Row::len) {
+            return Some(Position {
+                x: at.x + 1,
+                y: at.y,
+            });
+        }
+
+        (at.y + 1 < self.len()).then_some(Position { x: 0, y: at.y + 1 })
+    }
+
+    /// The position before `at`, going through the end of each row.
+    pub fn previous_position(&self, at: &Position) -> Option<Position> {
+        if at.x > 0 {
+            return Some(Position {
+                x: at.x - 1,
+                y: at.y,
+            });
+        }
+
+        let y = at.y.checked_sub(1)?;
+
+        Some(Position {
+            x: self.row(y).map_or(0, Row::len),
+            y,
+        })
+    }
+
     pub fn insert(&mut self, at: &Position, c: char) -> Result<(), Box<dyn Error>> {
         if at.y > self.rows.len() {
             return Ok(());
         }
 
-        self.changed = true;
+        let (operation_at, text) = self.insertion(at, &c.to_string());
+        let operation = Operation::Insert {
+            at: operation_at,
+            text,
+            new_row: self.rows.is_empty(),
+        };
 
         if c == '\n' {
             match self.insert_newline(at) {
@@ -75,6 +162,7 @@ impl Document {
             }
         }
 
+        self.record(operation);
         self.unhighlight_rows(at.y);
 
         Ok(())
@@ -90,7 +178,6 @@ impl Document {
             return Ok(());
         }
 
-        self.changed = true;
         let row = self.rows.get_mut(at.y);
 
         match row {
@@ -99,15 +186,32 @@ impl Document {
                     let next_row = self.rows.remove(at.y + 1);
                     let row = self.rows.get_mut(at.y);
 
-                    match row {
-                        Some(r) => r.append(&next_row),
-                        None => return Err(Box::new(RowError::DeletionError(at.x, at.y))),
+                    match (row, next_row) {
+                        (Some(r), Some(next_row)) => r.append(&next_row),
+                        _ => return Err(Box::new(RowError::DeletionError(at.x, at.y))),
                     }
+
+                    self.row_shifts.push((at.y, -1));
+
+                    self.record(Operation::Delete {
+                        at: at.clone(),
+                        text: String::from("\n"),
+                    });
                 } else {
                     let row = self.rows.get_mut(at.y);
 
                     match row {
-                        Some(r) => r.delete(at.x),
+                        Some(r) => {
+                            if let Some(grapheme) = r.as_string().graphemes(true).nth(at.x) {
+                                let text = grapheme.to_owned();
+
+                                r.delete(at.x);
+                                self.record(Operation::Delete {
+                                    at: at.clone(),
+                                    text,
+                                });
+                            }
+                        }
                         None => return Err(Box::new(RowError::DeletionError(at.x, at.y))),
                     }
                 }
@@ -120,34 +224,321 @@ impl Document {
         }
     }
 
+    /// Inserts `text`, which may span several rows, as a single edit and
+    /// returns the position right after it.
+    pub fn insert_str(&mut self, at: &Position, text: &str) -> Position {
+        if text.is_empty() || at.y > self.rows.len() {
+            return at.clone();
+        }
+
+        let text = text.replace("\r\n", "\n").replace('\r', "\n");
+        let at = Position {
+            x: at.x.min(self.rows.get(at.y).map_or(0, Row::len)),
+            y: at.y,
+        };
+        let (operation_at, text) = self.insertion(&at, &text);
+        let new_row = self.rows.is_empty();
+        let end = self.insert_text(&operation_at, &text);
+
+        self.record(Operation::Insert {
+            at: operation_at,
+            text,
+            new_row,
+        });
+        self.unhighlight_rows(at.y);
+
+        end
+    }
+
+    /// Returns the text between `start` and `end`, rows joined by `\n`.
+    pub fn text(&self, start: &Position, end: &Position) -> String {
+        let start = self.clamp(start);
+        let end = self.clamp(end);
+        let mut text = String::new();
+
+        for y in start.y..=end.y {
+            let Some(row) = self.rows.get(y) else {
+                break;
+            };
+            let from = if y == start.y { start.x } else { 0 };
+            let to = if y == end.y { end.x } else { row.len() };
+
+            if y > start.y {
+                text.push('\n');
+            }
+
+            text.extend(
+                row.as_string()
+                    .graphemes(true)
+                    .skip(from)
+                    .take(to.saturating_sub(from)),
+            );
+        }
+
+        text
+    }
+
+    /// Removes the text between `start` and `end` as a single edit.
+    pub fn delete_range(&mut self, start: &Position, end: &Position) {
+        let start = self.clamp(start);
+        let text = self.text(&start, end);
+
+        if text.is_empty() {
+            return;
+        }
+
+        self.remove_text(&start, &text);
+        self.record(Operation::Delete {
+            at: start.clone(),
+            text,
+        });
+        self.unhighlight_rows(start.y);
+    }
+
+    /// Reverts the last group of edits and returns where the cursor should go.
+    pub fn undo(&mut self) -> Option<Position> {
+        let group = self.history.undo()?;
+        let mut position = None;
+
+        for operation in group.iter().rev() {
+            match operation {
+                Operation::Insert { at, text, new_row } => {
+                    self.remove_text(at, text);
+
+                    if *new_row {
+                        self.rows.remove(at.y);
+                    }
+                }
+                Operation::Delete { at, text } => {
+                    self.insert_text(at, text);
+                }
+            }
+
+            position = Some(operation.position().clone());
+        }
+
+        self.after_history_change(&group);
+
+        position
+    }
+
+    /// Re-applies the last undone group of edits and returns where the cursor should go.
+    pub fn redo(&mut self) -> Option<Position> {
+        let group = self.history.redo()?;
+        let mut position = None;
+
+        for operation in &group {
+            position = match operation {
+                Operation::Insert { at, text, .. } => Some(self.insert_text(at, text)),
+                Operation::Delete { at, text } => {
+                    self.remove_text(at, text);
+                    Some(at.clone())
+                }
+            };
+        }
+
+        self.after_history_change(&group);
+
+        position
+    }
+
+    /// Groups every edit made until `end_group` into a single undo step.
+    pub fn begin_group(&mut self) {
+        self.history.begin_group();
+    }
+
+    pub fn end_group(&mut self) {
+        self.history.end_group();
+    }
+
+    /// Stops the next edit from being merged with the previous one.
+    pub fn seal_history(&mut self) {
+        self.history.seal();
+    }
+
+    /// Writes the document to a temporary file next to the target, syncs it
+    /// and renames it over the original, so an interrupted save never leaves
+    /// a truncated file behind. Returns the error syncing the directory
+    /// afterwards, if any, as the file is saved by then.
+    ///
     /// # Errors
     ///
-    /// Will return `Error` if it fails to create a file to save
-    pub fn save(&mut self) -> Result<(), IOError> {
+    /// Will return `Error` if any step of writing, syncing or renaming fails
+    pub fn save(&mut self) -> Result<Option<IOError>, IOError> {
         if let Some(filename) = &self.filename {
-            let mut file = File::create(filename)?;
-            self.file_type = FileType::new().from(filename);
+            let path = fs::canonicalize(filename).unwrap_or_else(|_| PathBuf::from(filename));
+            let path = path.as_path();
+            let temp_path = temp_path(path);
+            let written = self
+                .write_to(&temp_path, path)
+                .and_then(|()| {
+                    if self.backup && path.exists() {
+                        fs::copy(path, backup_path(path))?;
+                    }
 
-            for row in &mut self.rows {
-                file.write_all(row.as_bytes())?;
-                file.write_all(b"\n")?;
+                    Ok(())
+                })
+                .and_then(|()| fs::rename(&temp_path, path));
+
+            if let Err(err) = written {
+                let _ = fs::remove_file(&temp_path);
+
+                return Err(err);
+            }
+
+            if !self.file_type_chosen {
+                self.file_type = FileType::new().from(filename);
             }
 
+            self.disk_state = DiskState::read(filename);
+            self.history.mark_saved();
             self.changed = false;
+            self.saved_line_ending = self.line_ending;
+            self.mixed_line_endings = false;
+
+            if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+                return Ok(File::open(parent).and_then(|dir| dir.sync_all()).err());
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Tells whether the file was modified, replaced or deleted since it was
+    /// last opened or saved.
+    pub fn changed_on_disk(&self) -> bool {
+        match (&self.filename, self.disk_state) {
+            (Some(filename), Some(recorded)) => DiskState::read(filename) != Some(recorded),
+            _ => false,
+        }
+    }
+
+    /// Like `changed_on_disk`, but ignores a change the user chose to keep editing over.
+    pub fn has_new_disk_change(&self) -> bool {
+        match &self.filename {
+            Some(filename) => {
+                self.changed_on_disk() && Some(DiskState::read(filename)) != self.ignored_disk_state
+            }
+            None => false,
+        }
+    }
+
+    pub fn ignore_disk_change(&mut self) {
+        self.ignored_disk_state = Some(self.filename.as_deref().and_then(DiskState::read));
+    }
+
+    /// Discards the buffer and reads the file again from disk.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Error` if it fails to read filename
+    pub fn reload(&mut self) -> Result<(), IOError> {
+        if let Some(filename) = &self.filename {
+            let mut document = Self::open(filename)?;
+
+            document.backup = self.backup;
+            document.version = self.version + 1;
+            *self = document;
         }
 
         Ok(())
     }
 
+    /// Returns and forgets the rows added or joined by the latest edits, for
+    /// other views of the document to follow them.
+    pub fn take_row_shifts(&mut self) -> Vec<(usize, isize)> {
+        std::mem::take(&mut self.row_shifts)
+    }
+
+    /// Finds the hunks turning the rows into `lines`.
+    pub fn diff(&self, lines: &[&str]) -> Vec<Hunk> {
+        let rows: Vec<&str> = self
+            .rows
+            .iter()
+            .map(|row| row.as_string().as_str())
+            .collect();
+
+        diff::diff(&rows, lines)
+    }
+
+    /// Keeps a copy of the previous contents in `<filename>~` on save.
+    pub fn set_backup(&mut self, backup: bool) {
+        self.backup = backup;
+    }
+
+    pub fn has_backup(&self) -> bool {
+        self.backup
+    }
+
     pub fn is_changed(&self) -> bool {
-        self.changed
+        self.changed || self.line_ending != self.saved_line_ending
+    }
+
+    pub fn line_ending(&self) -> LineEnding {
+        self.line_ending
+    }
+
+    pub fn has_mixed_line_endings(&self) -> bool {
+        self.mixed_line_endings
+    }
+
+    /// Changes the terminator written after each row on the next save.
+    pub fn set_line_ending(&mut self, line_ending: LineEnding) {
+        self.line_ending = line_ending;
+        self.version += 1;
+    }
+
+    /// Increases every time the contents or format of the document change.
+    pub fn version(&self) -> u64 {
+        self.version
+    }
+
+    /// Replaces the contents with the ones saved in a swap file.
+    pub fn recover(&mut self, swap: Swap) {
+        let rows = swap.lines.iter().map(|line| Row::from(line.as_str()));
+
+        self.rows = Rope::from(rows.collect::<Vec<_>>());
+        self.line_ending = swap.line_ending;
+        self.missing_newline = !swap.final_newline && !self.rows.is_empty();
+        self.history = History::default();
+        self.history.mark_unsaved();
+        self.highlighted_until = 0;
+        self.changed = true;
+        self.version += 1;
+    }
+
+    pub fn has_final_newline(&self) -> bool {
+        !self.missing_newline
     }
 
-    pub fn find(&self, query: &str, at: &Position, direction: SearchDirection) -> Option<Position> {
+    /// Returns where the next match of `pattern` from `at` in `direction`
+    /// starts and ends.
+    pub fn find(
+        &self,
+        pattern: &Pattern,
+        at: &Position,
+        direction: SearchDirection,
+    ) -> Option<(Position, Position)> {
         if at.y >= self.rows.len() {
             return None;
         }
 
+        if pattern.spans() > 0 {
+            return if direction == SearchDirection::Forward {
+                (at.y..self.rows.len()).find_map(|y| {
+                    let x = if y == at.y { at.x } else { 0 };
+
+                    self.find_spanning(pattern, &Position { x, y }, direction)
+                })
+            } else {
+                (0..=at.y).rev().find_map(|y| {
+                    let x = if y == at.y { at.x } else { usize::MAX };
+
+                    self.find_spanning(pattern, &Position { x, y }, direction)
+                })
+            };
+        }
+
         let mut position = Position { x: at.x, y: at.y };
         let start = if direction == SearchDirection::Forward {
             at.y
@@ -162,10 +553,17 @@ impl Document {
 
         for _ in start..end {
             if let Some(row) = self.rows.get(position.y) {
-                if let Some(x) = row.find(query, position.x, direction) {
-                    position.x = x;
-
-                    return Some(position);
+                if let Some(found) = row.find(pattern, position.x, direction) {
+                    return Some((
+                        Position {
+                            x: found.start,
+                            y: position.y,
+                        },
+                        Position {
+                            x: found.end,
+                            y: position.y,
+                        },
+                    ));
                 }
 
                 if direction == SearchDirection::Forward {
@@ -186,9 +584,84 @@ impl Document {
         None
     }
 
+    /// Like `find`, but carries on from the other end of the document when
+    /// nothing is found, and tells whether it had to.
+    pub fn find_wrapping(
+        &self,
+        pattern: &Pattern,
+        at: &Position,
+        direction: SearchDirection,
+    ) -> Option<(Position, Position, bool)> {
+        if let Some((start, end)) = self.find(pattern, at, direction) {
+            return Some((start, end, false));
+        }
+
+        let last = self.rows.len().saturating_sub(1);
+        let from = if direction == SearchDirection::Forward {
+            Position::default()
+        } else {
+            Position {
+                x: self.rows.get(last).map_or(0, Row::len),
+                y: last,
+            }
+        };
+
+        self.find(pattern, &from, direction)
+            .map(|(start, end)| (start, end, true))
+    }
+
+    /// Returns where every match of `pattern` starts and ends, in document order.
+    pub fn find_all(&self, pattern: &Pattern) -> Vec<(Position, Position)> {
+        let mut matches = Vec::new();
+        let mut at = Position::default();
+
+        while let Some((start, end)) = self.find(pattern, &at, SearchDirection::Forward) {
+            at = end.clone();
+            matches.push((start, end));
+        }
+
+        matches
+    }
+
+    /// Replaces the match of `pattern` at `at` as a single edit and returns
+    /// the position right after the replacement.
+    pub fn replace(&mut self, at: &Position, pattern: &Pattern, replacement: &str) -> Position {
+        self.history.begin_group();
+
+        let end = self.replace_match(at, pattern, replacement);
+
+        self.history.end_group();
+
+        end
+    }
+
+    /// Replaces every match of `pattern` from `from` onwards as a single
+    /// edit and returns how many were replaced.
+    pub fn replace_all(&mut self, from: &Position, pattern: &Pattern, replacement: &str) -> usize {
+        let matches = self
+            .find_all(pattern)
+            .into_iter()
+            .filter(|(start, _)| (start.y, start.x) >= (from.y, from.x))
+            .collect::<Vec<_>>();
+
+        self.history.begin_group();
+
+        // Going backwards keeps the positions of the earlier matches valid.
+        for (start, _) in matches.iter().rev() {
+            self.replace_match(start, pattern, replacement);
+        }
+
+        self.history.end_group();
+
+        matches.len()
+    }
+
+    /// # Errors
+    ///
+    /// Will return `HighlightError` if the rows to highlight are out of range
     pub fn highlight(
         &mut self,
-        word: &Option<String>,
+        word: Option<&Pattern>,
         until: Option<usize>,
     ) -> Result<(), Box<dyn Error>> {
         let mut start_with_comment = false;
@@ -202,16 +675,24 @@ impl Document {
         } else {
             len
         };
-        let rows = self.rows.get_mut(..until);
+        let highlighted_until = self.highlighted_until;
+        let opts = self.file_type.highlight_options();
 
-        match rows {
-            Some(r) => {
-                for row in r {
-                    start_with_comment =
-                        row.highlight(self.file_type.highlight_options(), word, start_with_comment);
-                }
+        if until > self.rows.len() {
+            return Err(Box::new(HighlightError));
+        }
+
+        self.rows.for_each_mut(0..until, |index, row| {
+            if index >= highlighted_until {
+                row.is_highlighted = false;
             }
-            None => return Err(Box::new(HighlightError)),
+
+            start_with_comment = row.highlight(opts, word, start_with_comment);
+        });
+        self.highlighted_until = highlighted_until.max(until);
+
+        if let Some(word) = word.filter(|word| word.spans() > 0) {
+            self.highlight_spanning(word, until);
         }
 
         Ok(())
@@ -221,6 +702,18 @@ impl Document {
         self.file_type.name()
     }
 
+    pub fn soft_wrap(&self) -> bool {
+        self.file_type.soft_wrap()
+    }
+
+    /// Highlights the document as `file_type` from now on, even after it is
+    /// saved under another name.
+    pub fn set_file_type(&mut self, file_type: FileType) {
+        self.file_type = file_type;
+        self.file_type_chosen = true;
+        self.highlighted_until = 0;
+    }
+
     fn insert_newline(&mut self, at: &Position) -> Result<(), Box<dyn Error>> {
         if at.y > self.rows.len() {
             return Ok(());
@@ -237,34 +730,729 @@ impl Document {
             Some(row) => {
                 let new_row = row.split(at.x);
                 self.rows.insert(at.y + 1, new_row);
+                self.row_shifts.push((at.y, 1));
 
                 Ok(())
             }
-            None => return Err(Box::new(RowError::InsertionError(at.x, at.y))),
+            None => Err(Box::new(RowError::InsertionError(at.x, at.y))),
         }
     }
 
-    fn unhighlight_rows(&mut self, start: usize) {
-        let start = start.saturating_sub(1);
+    fn write_to(&self, temp_path: &Path, path: &Path) -> Result<(), IOError> {
+        let file = File::create(temp_path)?;
+
+        if let Ok(metadata) = fs::metadata(path) {
+            file.set_permissions(metadata.permissions())?;
+            // Only root can give the file away, keep our ownership otherwise.
+            let _ = chown(temp_path, Some(metadata.uid()), Some(metadata.gid()));
+        }
+
+        let mut writer = BufWriter::new(file);
+        let last = self.rows.len().saturating_sub(1);
+        let line_ending = self.line_ending.as_str().as_bytes();
+
+        for (index, row) in self.rows.iter().enumerate() {
+            writer.write_all(row.as_bytes())?;
+
+            if index < last || !self.missing_newline {
+                writer.write_all(line_ending)?;
+            }
+        }
+
+        writer
+            .into_inner()
+            .map_err(|err| err.into_error())?
+            .sync_all()
+    }
+
+    fn replace_match(&mut self, at: &Position, pattern: &Pattern, replacement: &str) -> Position {
+        let Some((start, end)) = self.find(pattern, at, SearchDirection::Forward) else {
+            return at.clone();
+        };
+        let (text, _) = self.window(start.y, pattern.spans());
+        let offset = self
+            .rows
+            .get(start.y)
+            .map_or(0, |row| row.byte_index(start.x));
+        let replacement = pattern.expand(&text, offset, replacement);
+
+        self.delete_range(&start, &end);
+        self.insert_str(&start, &replacement)
+    }
+
+    /// Joins rows `y..=y + extra` with `\n`, returning the text and the byte
+    /// offset each row starts at.
+    fn window(&self, y: usize, extra: usize) -> (String, Vec<usize>) {
+        let mut text = String::new();
+        let mut offsets = Vec::new();
+
+        for index in y..=y.saturating_add(extra) {
+            let Some(row) = self.rows.get(index) else {
+                break;
+            };
+
+            if index > y {
+                text.push('\n');
+            }
+
+            offsets.push(text.len());
+            text.push_str(row.as_string());
+        }
+
+        (text, offsets)
+    }
+
+    /// Finds a match of a pattern crossing line breaks that starts in row
+    /// `at.y`, after `at.x` going forward or before it going backward.
+    fn find_spanning(
+        &self,
+        pattern: &Pattern,
+        at: &Position,
+        direction: SearchDirection,
+    ) -> Option<(Position, Position)> {
+        let row = self.rows.get(at.y)?;
+        let (text, offsets) = self.window(at.y, pattern.spans());
+        let row_end = row.as_string().len();
+        let (start, end) = if direction == SearchDirection::Forward {
+            pattern.find_at(&text, row.byte_index(at.x))?
+        } else if at.x > row.len() {
+            pattern.rfind_before(&text, row_end + 1)?
+        } else {
+            pattern.rfind_before(&text, row.byte_index(at.x))?
+        };
+
+        if start > row_end {
+            return None;
+        }
+
+        let position = |byte: usize| {
+            let index = offsets
+                .iter()
+                .rposition(|offset| *offset <= byte)
+                .unwrap_or(0);
+            let x = self
+                .rows
+                .get(at.y + index)
+                .map_or(0, |row| row.grapheme_index(byte - offsets[index]));
+
+            Position { x, y: at.y + index }
+        };
+
+        Some((position(start), position(end)))
+    }
+
+    fn highlight_spanning(&mut self, pattern: &Pattern, until: usize) {
+        let mut at = Position::default();
+
+        while at.y < until {
+            let Some((start, end)) = self.find_spanning(pattern, &at, SearchDirection::Forward)
+            else {
+                at = Position { x: 0, y: at.y + 1 };
+                continue;
+            };
+
+            for y in start.y..=end.y {
+                if let Some(row) = self.rows.get_mut(y) {
+                    let from = if y == start.y { start.x } else { 0 };
+                    let to = if y == end.y { end.x } else { row.len() };
+
+                    row.highlight_range(from..to);
+                }
+            }
+
+            at = end;
+        }
+    }
+
+    /// Moves `at` onto the closest position that holds text.
+    fn clamp(&self, at: &Position) -> Position {
+        match self.rows.get(at.y) {
+            Some(row) => Position {
+                x: at.x.min(row.len()),
+                y: at.y,
+            },
+            None => {
+                let y = self.rows.len().saturating_sub(1);
+
+                Position {
+                    x: self.rows.get(y).map_or(0, Row::len),
+                    y,
+                }
+            }
+        }
+    }
+
+    /// Where inserting `text` at `at` happens in terms of existing rows:
+    /// typing past the last row first turns it into a real row.
+    fn insertion(&self, at: &Position, text: &str) -> (Position, String) {
+        let mut text = text.to_owned();
+        let mut at = at.clone();
+
+        if at.y == self.rows.len() && at.y > 0 {
+            at.y -= 1;
+            at.x = self.rows.get(at.y).map_or(0, Row::len);
+
+            if text != "\n" {
+                text.insert(0, '\n');
+            }
+        }
+
+        (at, text)
+    }
+
+    fn record(&mut self, operation: Operation) {
+        self.history.record(operation);
+        self.changed = !self.history.is_saved();
+        self.version += 1;
+    }
+
+    fn after_history_change(&mut self, group: &[Operation]) {
+        if let Some(y) = group.iter().map(|operation| operation.position().y).min() {
+            self.unhighlight_rows(y);
+        }
+
+        self.changed = !self.history.is_saved();
+        self.version += 1;
+    }
+
+    fn insert_text(&mut self, at: &Position, text: &str) -> Position {
+        let mut position = at.clone();
+
+        if position.y >= self.rows.len() {
+            position.y = self.rows.len();
+            self.rows.push(Row::default());
+        }
+
+        self.shift_rows(position.y, text.matches('\n').count() as isize);
+
+        for (index, line) in text.split('\n').enumerate() {
+            if index > 0 {
+                if let Some(row) = self.rows.get_mut(position.y) {
+                    let new_row = row.split(position.x);
+
+                    self.rows.insert(position.y + 1, new_row);
+                }
+
+                position.y += 1;
+                position.x = 0;
+            }
+
+            if let Some(row) = self.rows.get_mut(position.y) {
+                row.insert_str(position.x, line);
+                position.x += line.graphemes(true).count();
+            }
+        }
+
+        position
+    }
+
+    fn remove_text(&mut self, at: &Position, text: &str) {
+        self.shift_rows(at.y, -(text.matches('\n').count() as isize));
+
+        let mut lines = text.split('\n').peekable();
+
+        while let Some(line) = lines.next() {
+            let len = line.graphemes(true).count();
+
+            if let Some(row) = self.rows.get_mut(at.y) {
+                row.delete_range(at.x, at.x.saturating_add(len));
+            }
+
+            if lines.peek().is_some() {
+                if let Some(next_row) = self.rows.remove(at.y + 1) {
+                    if let Some(row) = self.rows.get_mut(at.y) {
+                        row.append(&next_row);
+                    }
+                }
+            }
+        }
+    }
 
-        for row in self.rows.iter_mut().skip(start) {
-            row.is_highlighted = false;
+    fn shift_rows(&mut self, y: usize, delta: isize) {
+        if delta != 0 {
+            self.row_shifts.push((y, delta));
         }
     }
+
+    fn unhighlight_rows(&mut self, start: usize) {
+        self.highlighted_until = self.highlighted_until.min(start.saturating_sub(1));
+    }
+}
+
+impl DiskState {
+    fn read(filename: &str) -> Option<Self> {
+        let metadata = fs::metadata(filename).ok()?;
+
+        Some(Self {
+            modified: metadata.modified().ok(),
+            len: metadata.len(),
+            device: metadata.dev(),
+            inode: metadata.ino(),
+        })
+    }
+}
+
+fn sibling_path(path: &Path, prefix: &str, suffix: &str) -> PathBuf {
+    let name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+
+    path.with_file_name(format!("{prefix}{name}{suffix}"))
+}
+
+fn temp_path(path: &Path) -> PathBuf {
+    sibling_path(path, ".", &format!(".{}.tmp", std::process::id()))
+}
+
+fn backup_path(path: &Path) -> PathBuf {
+    sibling_path(path, "", "~")
 }
 
 #[cfg(test)]
 mod document_tests {
     use super::*;
+    use crate::Query;
+
+    fn temp_file(name: &str) -> String {
+        let path = std::env::temp_dir().join(format!("revise-{}-{name}", std::process::id()));
+
+        path.to_str().unwrap().to_owned()
+    }
 
     #[test]
     fn test_save() {
+        let filename = temp_file("save.txt");
         let mut new_doc = Document {
-            filename: Some("test.txt".to_owned()),
+            filename: Some(filename.clone()),
             ..Document::default()
         };
         let save_res = new_doc.save();
 
-        assert_eq!(save_res.ok(), Some(()),);
+        assert!(save_res.is_ok());
+        std::fs::remove_file(filename).unwrap();
+    }
+
+    fn contents(doc: &Document) -> Vec<String> {
+        doc.rows.iter().map(|row| row.as_string().clone()).collect()
+    }
+
+    #[test]
+    fn test_undo_coalesces_typing() {
+        let mut doc = Document::default();
+
+        for (x, c) in "abc".chars().enumerate() {
+            doc.insert(&Position { x, y: 0 }, c).unwrap();
+        }
+
+        assert!(doc.is_changed());
+        assert_eq!(doc.undo(), Some(Position { x: 0, y: 0 }));
+        assert!(doc.is_empty());
+        assert!(!doc.is_changed());
+        assert_eq!(doc.redo(), Some(Position { x: 3, y: 0 }));
+        assert_eq!(contents(&doc), vec!["abc".to_owned()]);
+    }
+
+    #[test]
+    fn test_undo_newline_and_join() {
+        let mut doc = Document {
+            rows: Rope::from(vec![Row::from("hello world")]),
+            ..Document::default()
+        };
+
+        doc.insert(&Position { x: 5, y: 0 }, '\n').unwrap();
+        assert_eq!(
+            contents(&doc),
+            vec!["hello".to_owned(), " world".to_owned()]
+        );
+
+        doc.delete(&Position { x: 5, y: 0 }).unwrap();
+        assert_eq!(contents(&doc), vec!["hello world".to_owned()]);
+
+        doc.undo();
+        assert_eq!(
+            contents(&doc),
+            vec!["hello".to_owned(), " world".to_owned()]
+        );
+
+        doc.undo();
+        assert_eq!(contents(&doc), vec!["hello world".to_owned()]);
+        assert!(!doc.is_changed());
+    }
+
+    #[test]
+    fn test_undo_group() {
+        let mut doc = Document {
+            rows: Rope::from(vec![Row::from("ab")]),
+            ..Document::default()
+        };
+
+        doc.begin_group();
+        doc.insert(&Position { x: 1, y: 0 }, 'y').unwrap();
+        doc.insert(&Position { x: 1, y: 0 }, '\n').unwrap();
+        doc.insert(&Position { x: 1, y: 0 }, 'x').unwrap();
+        doc.end_group();
+        assert_eq!(contents(&doc), vec!["ax".to_owned(), "yb".to_owned()]);
+
+        doc.undo();
+        assert_eq!(contents(&doc), vec!["ab".to_owned()]);
+        assert_eq!(doc.undo(), None);
+    }
+
+    #[test]
+    fn test_undo_nested_group() {
+        let mut doc = Document {
+            rows: Rope::from(vec![Row::from("ab")]),
+            ..Document::default()
+        };
+
+        doc.begin_group();
+        doc.insert(&Position { x: 1, y: 0 }, 'x').unwrap();
+        doc.begin_group();
+        doc.insert_str(&Position { x: 2, y: 0 }, "pasted\n");
+        doc.end_group();
+        doc.insert(&Position { x: 0, y: 1 }, 'z').unwrap();
+        doc.end_group();
+        doc.insert(&Position { x: 2, y: 1 }, '!').unwrap();
+        assert_eq!(contents(&doc), vec!["axpasted", "zb!"]);
+
+        doc.undo();
+        assert_eq!(contents(&doc), vec!["axpasted", "zb"]);
+
+        doc.undo();
+        assert_eq!(contents(&doc), vec!["ab"]);
+        assert_eq!(doc.undo(), None);
+    }
+
+    #[test]
+    fn test_insert_str() {
+        let mut doc = Document {
+            rows: Rope::from(vec![Row::from("fn main() {}")]),
+            ..Document::default()
+        };
+        let end = doc.insert_str(&Position { x: 11, y: 0 }, "\r\n    one();\n    two();\n");
+
+        assert_eq!(end, Position { x: 0, y: 3 });
+        assert_eq!(
+            contents(&doc),
+            vec!["fn main() {", "    one();", "    two();", "}"]
+        );
+
+        let end = doc.insert_str(&Position { x: 0, y: 4 }, "// end");
+
+        assert_eq!(end, Position { x: 6, y: 4 });
+        assert_eq!(contents(&doc).last().map(String::as_str), Some("// end"));
+
+        doc.undo();
+        doc.undo();
+        assert_eq!(contents(&doc), vec!["fn main() {}"]);
+    }
+
+    fn pattern(text: &str, regex: bool) -> Pattern {
+        Query {
+            text: text.to_owned(),
+            regex,
+            ..Query::default()
+        }
+        .compile()
+        .unwrap()
+    }
+
+    #[test]
+    fn test_replace() {
+        let mut doc = Document {
+            rows: Rope::from(vec![Row::from("let a = a + 1;"), Row::from("a")]),
+            ..Document::default()
+        };
+        let a = pattern("a", false);
+
+        assert_eq!(
+            doc.find_all(&a)
+                .into_iter()
+                .map(|(start, _)| start)
+                .collect::<Vec<_>>(),
+            vec![
+                Position { x: 4, y: 0 },
+                Position { x: 8, y: 0 },
+                Position { x: 0, y: 1 },
+            ]
+        );
+
+        let end = doc.replace(&Position { x: 4, y: 0 }, &a, "total");
+
+        assert_eq!(end, Position { x: 9, y: 0 });
+        assert_eq!(doc.replace_all(&end, &a, "b"), 2);
+        assert_eq!(contents(&doc), vec!["let total = b + 1;", "b"]);
+
+        doc.undo();
+        assert_eq!(contents(&doc), vec!["let total = a + 1;", "a"]);
+        doc.undo();
+        assert_eq!(contents(&doc), vec!["let a = a + 1;", "a"]);
+    }
+
+    #[test]
+    fn test_replace_all_with_captures() {
+        let mut doc = Document {
+            rows: Rope::from(vec![Row::from("x = foo(1, 2);"), Row::from("foo(3, 4)")]),
+            ..Document::default()
+        };
+        let call = pattern(r"foo\((\d+), (\d+)\)", true);
+
+        assert_eq!(
+            doc.replace_all(&Position::default(), &call, "bar($2, $1)"),
+            2
+        );
+        assert_eq!(contents(&doc), vec!["x = bar(2, 1);", "bar(4, 3)"]);
+    }
+
+    #[test]
+    fn test_find_wraps_and_spans_rows() {
+        let doc = Document {
+            rows: Rope::from(vec![
+                Row::from("fn a() {"),
+                Row::from("}"),
+                Row::from("fn b() {"),
+                Row::from("}"),
+            ]),
+            ..Document::default()
+        };
+        let brace = pattern("{\\n}", false);
+        let at = Position { x: 3, y: 2 };
+
+        assert_eq!(
+            doc.find(&brace, &at, SearchDirection::Forward),
+            Some((Position { x: 7, y: 2 }, Position { x: 1, y: 3 }))
+        );
+        assert_eq!(
+            doc.find(&brace, &at, SearchDirection::Backward),
+            Some((Position { x: 7, y: 0 }, Position { x: 1, y: 1 }))
+        );
+        assert_eq!(doc.find_all(&brace).len(), 2);
+
+        let fn_a = pattern("fn a", false);
+
+        assert_eq!(doc.find(&fn_a, &at, SearchDirection::Forward), None);
+        assert_eq!(
+            doc.find_wrapping(&fn_a, &at, SearchDirection::Forward),
+            Some((Position { x: 0, y: 0 }, Position { x: 4, y: 0 }, true))
+        );
+    }
+
+    #[test]
+    fn test_replace_across_rows() {
+        let mut doc = Document {
+            rows: Rope::from(vec![Row::from("a,"), Row::from("b"), Row::from("c")]),
+            ..Document::default()
+        };
+        let comma = pattern(r",\n(\w)", true);
+
+        assert_eq!(doc.replace_all(&Position::default(), &comma, ", $1"), 1);
+        assert_eq!(contents(&doc), vec!["a, b", "c"]);
+    }
+
+    #[test]
+    fn test_delete_range() {
+        let mut doc = Document {
+            rows: Rope::from(vec![Row::from("one"), Row::from("two"), Row::from("three")]),
+            ..Document::default()
+        };
+        let start = Position { x: 1, y: 0 };
+        let end = Position { x: 2, y: 2 };
+
+        assert_eq!(doc.text(&start, &end), "ne\ntwo\nth");
+        assert_eq!(
+            doc.text(&Position { x: 4, y: 1 }, &Position { x: 9, y: 9 }),
+            "\nthree"
+        );
+
+        doc.delete_range(&start, &end);
+        assert_eq!(contents(&doc), vec!["oree"]);
+
+        assert_eq!(doc.undo(), Some(start));
+        assert_eq!(contents(&doc), vec!["one", "two", "three"]);
+    }
+
+    #[test]
+    fn test_next_and_previous_position() {
+        let doc = Document {
+            rows: Rope::from(vec![Row::from("ab"), Row::from("")]),
+            ..Document::default()
+        };
+        let at = |x, y| Position { x, y };
+
+        assert_eq!(doc.next_position(&at(1, 0)), Some(at(2, 0)));
+        assert_eq!(doc.next_position(&at(2, 0)), Some(at(0, 1)));
+        assert_eq!(doc.next_position(&at(0, 1)), None);
+        assert_eq!(doc.previous_position(&at(0, 1)), Some(at(2, 0)));
+        assert_eq!(doc.previous_position(&at(0, 0)), None);
+    }
+
+    #[test]
+    fn test_changed_after_save_and_undo() {
+        let filename = temp_file("changed.txt");
+        let mut doc = Document {
+            filename: Some(filename.clone()),
+            ..Document::default()
+        };
+
+        doc.insert(&Position { x: 0, y: 0 }, 'a').unwrap();
+        doc.save().unwrap();
+        doc.insert(&Position { x: 1, y: 0 }, 'b').unwrap();
+        assert!(doc.is_changed());
+
+        doc.undo();
+        assert!(!doc.is_changed());
+
+        doc.undo();
+        assert!(doc.is_changed());
+        std::fs::remove_file(filename).unwrap();
+    }
+
+    #[test]
+    fn test_save_preserves_line_endings() {
+        let path = PathBuf::from(temp_file("line-endings.txt"));
+        let filename = path.to_str().unwrap();
+
+        std::fs::write(&path, "first\r\nsecond\r\nlast").unwrap();
+
+        let mut doc = Document::open(filename).unwrap();
+
+        assert_eq!(doc.line_ending(), LineEnding::CrLf);
+        assert!(!doc.has_final_newline());
+
+        doc.save().unwrap();
+        assert_eq!(read_to_string(&path).unwrap(), "first\r\nsecond\r\nlast");
+
+        doc.set_line_ending(LineEnding::Lf);
+        assert!(doc.is_changed());
+
+        doc.save().unwrap();
+        assert_eq!(read_to_string(&path).unwrap(), "first\nsecond\nlast");
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_open_mixed_line_endings() {
+        let filename = temp_file("mixed.txt");
+
+        std::fs::write(&filename, "one\r\ntwo\nthree\r\n").unwrap();
+
+        let mut doc = Document::open(&filename).unwrap();
+
+        assert_eq!(contents(&doc), vec!["one", "two", "three"]);
+        assert_eq!(doc.line_ending(), LineEnding::CrLf);
+        assert!(doc.has_mixed_line_endings());
+
+        doc.save().unwrap();
+        assert_eq!(
+            read_to_string(&filename).unwrap(),
+            "one\r\ntwo\r\nthree\r\n"
+        );
+        assert!(!doc.has_mixed_line_endings());
+        std::fs::remove_file(&filename).unwrap();
+    }
+
+    #[test]
+    fn test_save_keeps_permissions_and_backup() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let path = PathBuf::from(temp_file("atomic-save.txt"));
+        let backup = backup_path(&path);
+        let filename = path.to_str().unwrap();
+
+        std::fs::write(&path, "old\n").unwrap();
+        std::fs::set_permissions(&path, fs::Permissions::from_mode(0o640)).unwrap();
+
+        let mut doc = Document::open(filename).unwrap();
+
+        doc.set_backup(true);
+        doc.insert(&Position { x: 0, y: 0 }, 'b').unwrap();
+        doc.save().unwrap();
+
+        assert_eq!(read_to_string(&path).unwrap(), "bold\n");
+        assert_eq!(read_to_string(&backup).unwrap(), "old\n");
+        assert_eq!(
+            fs::metadata(&path).unwrap().permissions().mode() & 0o777,
+            0o640
+        );
+        std::fs::remove_file(&path).unwrap();
+        std::fs::remove_file(&backup).unwrap();
+    }
+
+    #[test]
+    fn test_failed_save_removes_temp_file() {
+        let filename = temp_file("dir");
+
+        std::fs::create_dir_all(&filename).unwrap();
+
+        let mut doc = Document {
+            filename: Some(filename.clone()),
+            ..Document::default()
+        };
+
+        doc.insert(&Position { x: 0, y: 0 }, 'a').unwrap();
+        assert!(doc.save().is_err());
+        assert!(!temp_path(Path::new(&filename)).exists());
+        assert!(doc.is_changed());
+        std::fs::remove_dir(&filename).unwrap();
+    }
+
+    #[test]
+    fn test_deleted_on_disk() {
+        let filename = temp_file("deleted.txt");
+
+        std::fs::write(&filename, "one\n").unwrap();
+
+        let mut doc = Document::open(&filename).unwrap();
+
+        std::fs::remove_file(&filename).unwrap();
+        assert!(doc.changed_on_disk());
+        assert!(doc.has_new_disk_change());
+
+        doc.ignore_disk_change();
+        assert!(!doc.has_new_disk_change());
+    }
+
+    #[test]
+    fn test_changed_on_disk() {
+        let path = PathBuf::from(temp_file("changed-on-disk.txt"));
+        let filename = path.to_str().unwrap();
+
+        std::fs::write(&path, "one\n").unwrap();
+
+        let mut doc = Document::open(filename).unwrap();
+
+        assert!(!doc.changed_on_disk());
+
+        std::fs::write(&path, "one\ntwo\n").unwrap();
+        assert!(doc.changed_on_disk());
+        assert!(doc.has_new_disk_change());
+        assert_eq!(
+            doc.diff(&["zero", "one", "two"]),
+            [
+                Hunk {
+                    line: 1,
+                    removed: 0,
+                    added: 1
+                },
+                Hunk {
+                    line: 2,
+                    removed: 0,
+                    added: 1
+                }
+            ]
+        );
+
+        doc.ignore_disk_change();
+        assert!(doc.changed_on_disk());
+        assert!(!doc.has_new_disk_change());
+
+        doc.reload().unwrap();
+        assert_eq!(doc.len(), 2);
+        assert!(!doc.changed_on_disk());
+
+        doc.save().unwrap();
+        assert!(!doc.changed_on_disk());
+        std::fs::remove_file(&path).unwrap();
     }
 }
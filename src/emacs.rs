@@ -0,0 +1,153 @@
+use crate::Document;
+use crate::Position;
+
+const KILL_RING_LEN: usize = 60;
+
+/// Text killed with the emacs commands, oldest first. It is kept apart from
+/// the system clipboard used by copy, cut and paste.
+#[derive(Default)]
+pub struct KillRing {
+    entries: Vec<String>,
+    /// How many entries back from the newest one the last yank was.
+    yanked: usize,
+}
+
+impl KillRing {
+    /// Adds `text` as the newest entry, or to the end of the newest entry if
+    /// `append`, so consecutive kills are yanked back together.
+    pub fn kill(&mut self, text: &str, append: bool) {
+        self.yanked = 0;
+
+        match self.entries.last_mut() {
+            Some(newest) if append => newest.push_str(text),
+            _ if text.is_empty() => (),
+            _ => {
+                self.entries.push(text.to_owned());
+
+                if self.entries.len() > KILL_RING_LEN {
+                    self.entries.remove(0);
+                }
+            }
+        }
+    }
+
+    /// Returns the newest entry.
+    pub fn yank(&mut self) -> Option<&str> {
+        self.yanked = 0;
+        self.entries.last().map(String::as_str)
+    }
+
+    /// Returns the entry before the one yanked last, going back to the
+    /// newest after the oldest.
+    pub fn rotate(&mut self) -> Option<&str> {
+        if self.entries.is_empty() {
+            return None;
+        }
+
+        self.yanked = (self.yanked + 1) % self.entries.len();
+
+        Some(&self.entries[self.entries.len() - 1 - self.yanked])
+    }
+}
+
+/// Where `M-f` goes: the end of the next word.
+pub fn forward_word(document: &Document, from: &Position) -> Position {
+    let mut at = from.clone();
+
+    while !is_word(document, &at) {
+        match document.next_position(&at) {
+            Some(next) => at = next,
+            None => return at,
+        }
+    }
+
+    while is_word(document, &at) {
+        match document.next_position(&at) {
+            Some(next) => at = next,
+            None => return at,
+        }
+    }
+
+    at
+}
+
+/// Where `M-b` goes: the start of the previous word.
+pub fn backward_word(document: &Document, from: &Position) -> Position {
+    let mut at = from.clone();
+
+    while let Some(previous) = document.previous_position(&at) {
+        at = previous;
+
+        if is_word(document, &at) {
+            break;
+        }
+    }
+
+    while let Some(previous) = document
+        .previous_position(&at)
+        .filter(|at| is_word(document, at))
+    {
+        at = previous;
+    }
+
+    at
+}
+
+fn is_word(document: &Document, at: &Position) -> bool {
+    document
+        .row(at.y)
+        .filter(|row| at.x < row.len())
+        .map(|row| &row.as_string()[row.byte_index(at.x)..])
+        .and_then(|rest| rest.chars().next())
+        .is_some_and(char::is_alphanumeric)
+}
+
+#[cfg(test)]
+mod emacs_tests {
+    use super::*;
+
+    fn position(x: usize, y: usize) -> Position {
+        Position { x, y }
+    }
+
+    #[test]
+    fn test_kill_ring() {
+        let mut ring = KillRing::default();
+
+        assert_eq!(ring.yank(), None);
+        assert_eq!(ring.rotate(), None);
+
+        ring.kill("first line", false);
+        ring.kill("\n", true);
+        ring.kill("second", false);
+        ring.kill("third", false);
+
+        assert_eq!(ring.yank(), Some("third"));
+        assert_eq!(ring.rotate(), Some("second"));
+        assert_eq!(ring.rotate(), Some("first line\n"));
+        assert_eq!(ring.rotate(), Some("third"));
+        assert_eq!(ring.yank(), Some("third"));
+
+        for n in 0..KILL_RING_LEN {
+            ring.kill(&n.to_string(), false);
+        }
+
+        assert_eq!(ring.entries.len(), KILL_RING_LEN);
+        assert_eq!(ring.entries[0], "0");
+    }
+
+    #[test]
+    fn test_word_motions() {
+        let mut document = Document::default();
+
+        document.insert_str(&Position::default(), "foo-bar baz\n  qux");
+
+        assert_eq!(forward_word(&document, &position(0, 0)), position(3, 0));
+        assert_eq!(forward_word(&document, &position(3, 0)), position(7, 0));
+        assert_eq!(forward_word(&document, &position(11, 0)), position(5, 1));
+        assert_eq!(backward_word(&document, &position(2, 1)), position(8, 0));
+        assert_eq!(backward_word(&document, &position(5, 0)), position(4, 0));
+        assert_eq!(backward_word(&document, &position(4, 0)), position(0, 0));
+        assert_eq!(backward_word(&document, &position(0, 0)), position(0, 0));
+    }
+}
@@ -3,6 +3,8 @@ use std::error::Error as Err;
 use std::path::Path;
 use thiserror::Error;
 
+const NAMES: [&str; 5] = ["Rust", "Toml", "Lock", "Markdown", "YAML"];
+
 #[derive(Default)]
 pub struct HighlightOptions {
     numbers: bool,
@@ -18,6 +20,7 @@ pub struct HighlightOptions {
 pub struct FileType {
     name: String,
     hl_opts: HighlightOptions,
+    soft_wrap: bool,
 }
 
 #[derive(Debug, Error)]
@@ -63,6 +66,7 @@ impl Default for FileType {
         Self {
             name: String::from("No filetype"),
             hl_opts: HighlightOptions::default(),
+            soft_wrap: false,
         }
     }
 }
@@ -72,6 +76,7 @@ impl FileType {
         Self {
             name: String::new(),
             hl_opts: HighlightOptions::default(),
+            soft_wrap: false,
         }
     }
 
@@ -82,30 +87,53 @@ impl FileType {
     pub fn from(&self, filename: &str) -> Self {
         match self.parse_extension(filename) {
             Ok(ext) => match ext.as_str() {
-                "rs" => return self.create_file_type("Rust"),
-                "toml" => return self.create_file_type("Toml"),
-                "lock" => return self.create_file_type("Lock"),
-                "md" => return self.create_file_type("Markdown"),
-                "yml" => return self.create_file_type("YAML"),
+                "rs" => self.create_file_type("Rust"),
+                "toml" => self.create_file_type("Toml"),
+                "lock" => self.create_file_type("Lock"),
+                "md" => self.create_file_type("Markdown"),
+                "yml" => self.create_file_type("YAML"),
                 _ => Self::default(),
             },
-            Err(_) => return self.create_file_type(filename),
+            Err(_) => self.create_file_type(filename),
         }
     }
 
+    /// Looks a file type up by name, ignoring case. `none` turns
+    /// highlighting off.
+    pub fn named(name: &str) -> Option<Self> {
+        if name.eq_ignore_ascii_case("none") {
+            return Some(Self::default());
+        }
+
+        NAMES
+            .iter()
+            .find(|known| known.eq_ignore_ascii_case(name))
+            .map(|known| Self::new().create_file_type(known))
+    }
+
+    /// Names accepted by `named`, besides `none`.
+    pub fn names() -> &'static [&'static str] {
+        &NAMES
+    }
+
     pub fn highlight_options(&self) -> &HighlightOptions {
         &self.hl_opts
     }
 
+    /// Whether long lines of this file type wrap when the file is opened.
+    pub fn soft_wrap(&self) -> bool {
+        self.soft_wrap
+    }
+
     fn parse_extension(&self, filename: &str) -> Result<String, Box<dyn Err>> {
         let path = Path::new(filename);
 
         match path.extension() {
             Some(ext) => match ext.to_str() {
-                Some(ext) => return Ok(ext.to_owned()),
-                None => return Err(Box::new(ParseExtensionError(filename.to_owned()))),
+                Some(ext) => Ok(ext.to_owned()),
+                None => Err(Box::new(ParseExtensionError(filename.to_owned()))),
             },
-            None => return Err(Box::new(ParseExtensionError(filename.to_owned()))),
+            None => Err(Box::new(ParseExtensionError(filename.to_owned()))),
         }
     }
 
@@ -122,6 +150,7 @@ impl FileType {
                 primary_keywords: keywords::rust::primary_keywords(),
                 secondary_keywords: keywords::rust::secondary_keywords(),
             },
+            soft_wrap: file_type == "Markdown",
         }
     }
 }
@@ -0,0 +1,163 @@
+use crate::Document;
+use crate::Position;
+use crate::Row;
+
+/// The line a `Target` names.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Line {
+    /// Counted from 1.
+    Number(usize),
+    /// Lines below the cursor.
+    Down(usize),
+    /// Lines above the cursor.
+    Up(usize),
+    /// How far through the document, from 0 to 100.
+    Percent(usize),
+}
+
+/// Where to move the cursor, typed as `842`, `842:5`, `+10`, `-3` or `50%`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Target {
+    pub line: Line,
+    /// Counted from 1, or the start of the line if `None`.
+    pub column: Option<usize>,
+}
+
+impl Target {
+    pub fn parse(text: &str) -> Option<Self> {
+        let text = text.trim();
+        let (line, column) = match text.split_once(':') {
+            Some((line, column)) => (line, Some(number(column).filter(|n| *n > 0)?)),
+            None => (text, None),
+        };
+        let line = if let Some(lines) = line.strip_prefix('+') {
+            Line::Down(number(lines)?)
+        } else if let Some(lines) = line.strip_prefix('-') {
+            Line::Up(number(lines)?)
+        } else if let Some(percent) = line.strip_suffix('%') {
+            Line::Percent(number(percent).filter(|percent| *percent <= 100)?)
+        } else {
+            Line::Number(number(line).filter(|n| *n > 0)?)
+        };
+
+        Some(Self { line, column })
+    }
+
+    /// The position in `document` the target names when the cursor is at
+    /// `from`, kept within the document.
+    pub fn resolve(&self, document: &Document, from: &Position) -> Position {
+        let y = match self.line {
+            Line::Number(line) => line - 1,
+            Line::Down(lines) => from.y.saturating_add(lines),
+            Line::Up(lines) => from.y.saturating_sub(lines),
+            Line::Percent(percent) => (document.len() * percent).div_ceil(100).saturating_sub(1),
+        }
+        .min(document.len().saturating_sub(1));
+        let x = self
+            .column
+            .map_or(0, |column| column - 1)
+            .min(document.row(y).map_or(0, Row::len));
+
+        Position { x, y }
+    }
+}
+
+/// Splits a location printed by a compiler, like `src/main.rs:842:5` or
+/// `src/main.rs:842`, into the file name and the target.
+pub fn split_location(location: &str) -> Option<(&str, Target)> {
+    let (rest, last) = location.rsplit_once(':')?;
+    let last = number(last).filter(|n| *n > 0)?;
+    let (filename, target) = match rest.rsplit_once(':') {
+        Some((filename, line)) if number(line).is_some_and(|n| n > 0) => (
+            filename,
+            Target {
+                line: Line::Number(number(line)?),
+                column: Some(last),
+            },
+        ),
+        _ => (
+            rest,
+            Target {
+                line: Line::Number(last),
+                column: None,
+            },
+        ),
+    };
+
+    (!filename.is_empty()).then_some((filename, target))
+}
+
+fn number(text: &str) -> Option<usize> {
+    if text.is_empty() || !text.bytes().all(|byte| byte.is_ascii_digit()) {
+        return None;
+    }
+
+    text.parse().ok()
+}
+
+#[cfg(test)]
+mod goto_tests {
+    use super::*;
+
+    fn position(x: usize, y: usize) -> Position {
+        Position { x, y }
+    }
+
+    #[test]
+    fn test_parse() {
+        let target = |line, column| Some(Target { line, column });
+
+        assert_eq!(Target::parse("842"), target(Line::Number(842), None));
+        assert_eq!(Target::parse(" 842:5 "), target(Line::Number(842), Some(5)));
+        assert_eq!(Target::parse("+10"), target(Line::Down(10), None));
+        assert_eq!(Target::parse("-3:2"), target(Line::Up(3), Some(2)));
+        assert_eq!(Target::parse("50%"), target(Line::Percent(50), None));
+        assert_eq!(Target::parse("0"), None);
+        assert_eq!(Target::parse("12:0"), None);
+        assert_eq!(Target::parse("101%"), None);
+        assert_eq!(Target::parse("+-3"), None);
+        assert_eq!(Target::parse("ten"), None);
+        assert_eq!(Target::parse(""), None);
+    }
+
+    #[test]
+    fn test_resolve() {
+        let mut document = Document::default();
+
+        document.insert_str(&Position::default(), "one\ntwo\nthree\nfour\nfive");
+
+        let resolve = |text, from| Target::parse(text).unwrap().resolve(&document, &from);
+
+        assert_eq!(resolve("3", position(2, 0)), position(0, 2));
+        assert_eq!(resolve("3:4", position(0, 0)), position(3, 2));
+        assert_eq!(resolve("3:40", position(0, 0)), position(5, 2));
+        assert_eq!(resolve("900", position(0, 0)), position(0, 4));
+        assert_eq!(resolve("+2", position(1, 1)), position(0, 3));
+        assert_eq!(resolve("+20", position(0, 1)), position(0, 4));
+        assert_eq!(resolve("-20", position(0, 3)), position(0, 0));
+        assert_eq!(resolve("50%", position(0, 0)), position(0, 2));
+        assert_eq!(resolve("100%", position(0, 0)), position(0, 4));
+        assert_eq!(resolve("0%", position(0, 3)), position(0, 0));
+    }
+
+    #[test]
+    fn test_split_location() {
+        let location = |line, column| Target {
+            line: Line::Number(line),
+            column,
+        };
+
+        assert_eq!(
+            split_location("src/main.rs:842:5"),
+            Some(("src/main.rs", location(842, Some(5))))
+        );
+        assert_eq!(
+            split_location("notes.md:12"),
+            Some(("notes.md", location(12, None)))
+        );
+        assert_eq!(split_location("a:b:7"), Some(("a:b", location(7, None))));
+        assert_eq!(split_location("notes.md"), None);
+        assert_eq!(split_location("notes.md:"), None);
+        assert_eq!(split_location(":3"), None);
+    }
+}
@@ -1,3 +1,5 @@
+use crate::Theme;
+
 use termion::color;
 use thiserror::Error;
 
@@ -20,17 +22,17 @@ pub enum Type {
 }
 
 impl Type {
-    pub fn to_color(self) -> impl color::Color {
+    pub fn to_color(self, theme: &Theme) -> color::Rgb {
         match self {
-            Type::Number => color::Rgb(220, 163, 163),
-            Type::Match => color::Rgb(30, 139, 210),
-            Type::String => color::Rgb(211, 54, 130),
+            Type::Number => theme.number,
+            Type::Match => theme.search_match,
+            Type::String => theme.string,
             Type::_Boolean => color::Rgb(0, 0, 139),
-            Type::Char => color::Rgb(108, 113, 196),
-            Type::Comment | Type::MultilineComment => color::Rgb(133, 153, 0),
-            Type::PrimaryKeywords => color::Rgb(181, 137, 0),
-            Type::SecondaryKeywords => color::Rgb(42, 161, 152),
-            Type::None => color::Rgb(255, 255, 255),
+            Type::Char => theme.char,
+            Type::Comment | Type::MultilineComment => theme.comment,
+            Type::PrimaryKeywords => theme.primary_keyword,
+            Type::SecondaryKeywords => theme.secondary_keyword,
+            Type::None => theme.text,
         }
     }
 }
@@ -0,0 +1,160 @@
+use crate::Position;
+
+use unicode_segmentation::UnicodeSegmentation;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Operation {
+    /// `new_row` is set when the document had no rows before the insert.
+    Insert {
+        at: Position,
+        text: String,
+        new_row: bool,
+    },
+    Delete {
+        at: Position,
+        text: String,
+    },
+}
+
+pub struct History {
+    undo_stack: Vec<Vec<Operation>>,
+    redo_stack: Vec<Vec<Operation>>,
+    sealed: bool,
+    /// How many groups are open; groups inside another one join it.
+    groups: usize,
+    saved_depth: Option<usize>,
+}
+
+impl Operation {
+    pub fn position(&self) -> &Position {
+        match self {
+            Operation::Insert { at, .. } | Operation::Delete { at, .. } => at,
+        }
+    }
+
+    fn coalesce(&mut self, next: &Operation) -> bool {
+        match (self, next) {
+            (
+                Operation::Insert { at, text, .. },
+                Operation::Insert {
+                    at: next_at,
+                    text: next_text,
+                    new_row: false,
+                },
+            ) => {
+                if next_text.contains('\n') || text.contains('\n') || next_at.y != at.y {
+                    return false;
+                }
+
+                if at.x.saturating_add(text.graphemes(true).count()) != next_at.x {
+                    return false;
+                }
+
+                text.push_str(next_text);
+
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+impl Default for History {
+    fn default() -> Self {
+        Self {
+            undo_stack: Vec::new(),
+            redo_stack: Vec::new(),
+            sealed: false,
+            groups: 0,
+            saved_depth: Some(0),
+        }
+    }
+}
+
+impl History {
+    pub fn record(&mut self, operation: Operation) {
+        self.redo_stack.clear();
+
+        if let Some(depth) = self.saved_depth {
+            if depth > self.undo_stack.len() {
+                self.saved_depth = None;
+            }
+        }
+
+        if !self.sealed {
+            if let Some(group) = self.undo_stack.last_mut() {
+                if self.groups > 0 {
+                    group.push(operation);
+
+                    return;
+                }
+
+                if let [last] = group.as_mut_slice() {
+                    if last.coalesce(&operation) {
+                        return;
+                    }
+                }
+            }
+        }
+
+        self.undo_stack.push(vec![operation]);
+        self.sealed = false;
+    }
+
+    /// Starts a group of operations undone and redone as a single step.
+    /// Groups begun inside it are part of it.
+    pub fn begin_group(&mut self) {
+        if self.groups == 0 {
+            self.sealed = true;
+        }
+
+        self.groups += 1;
+    }
+
+    pub fn end_group(&mut self) {
+        self.groups = self.groups.saturating_sub(1);
+
+        if self.groups == 0 {
+            self.sealed = true;
+        }
+    }
+
+    /// Prevents the next operation from being merged into the previous step.
+    pub fn seal(&mut self) {
+        if self.groups == 0 {
+            self.sealed = true;
+        }
+    }
+
+    pub fn undo(&mut self) -> Option<Vec<Operation>> {
+        let group = self.undo_stack.pop()?;
+
+        self.redo_stack.push(group.clone());
+        self.sealed = true;
+
+        Some(group)
+    }
+
+    pub fn redo(&mut self) -> Option<Vec<Operation>> {
+        let group = self.redo_stack.pop()?;
+
+        self.undo_stack.push(group.clone());
+        self.sealed = true;
+
+        Some(group)
+    }
+
+    pub fn mark_saved(&mut self) {
+        self.saved_depth = Some(self.undo_stack.len());
+        self.sealed = true;
+    }
+
+    /// Forgets the saved state, e.g. after contents were replaced wholesale.
+    pub fn mark_unsaved(&mut self) {
+        self.saved_depth = None;
+    }
+
+    pub fn is_saved(&self) -> bool {
+        self.saved_depth == Some(self.undo_stack.len())
+    }
+}
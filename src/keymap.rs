@@ -0,0 +1,616 @@
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashMap};
+use termion::event::Key;
+
+/// An editor action that keys can be bound to, named in the config file.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum Command {
+    Copy,
+    Cut,
+    Paste,
+    Quit,
+    Save,
+    Search,
+    Replace,
+    ConvertLineEnding,
+    NextBuffer,
+    PreviousBuffer,
+    Open,
+    CloseBuffer,
+    PickBuffer,
+    SplitStacked,
+    SplitSideBySide,
+    ClosePane,
+    NextPane,
+    ToggleLineNumbers,
+    ToggleWrap,
+    Undo,
+    Redo,
+    ToggleMark,
+    ClearSelection,
+    DeleteForward,
+    DeleteBackward,
+    MoveUp,
+    MoveDown,
+    MoveLeft,
+    MoveRight,
+    PageUp,
+    PageDown,
+    LineStart,
+    LineEnd,
+    Palette,
+    WordForward,
+    WordBackward,
+    KillLine,
+    KillRegion,
+    CopyRegion,
+    Yank,
+    YankPop,
+    Goto,
+}
+
+/// The bindings the `[keys]` overrides of the config file start from.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Preset {
+    #[default]
+    Default,
+    /// Emacs keys, with kills going to a kill ring instead of the clipboard.
+    Emacs,
+}
+
+/// What a sequence of keys means in a `Keymap`.
+#[derive(Debug, PartialEq)]
+pub enum Lookup {
+    Command(Command),
+    /// The keys start a chord and more are needed.
+    Prefix,
+    Unbound,
+}
+
+/// Key sequences bound to commands.
+pub struct Keymap {
+    bindings: HashMap<Vec<Key>, Command>,
+}
+
+const DEFAULT_BINDINGS: [(&str, Command); 42] = [
+    ("Ctrl-C", Command::Copy),
+    ("Ctrl-X", Command::Cut),
+    ("Ctrl-V", Command::Paste),
+    ("Ctrl-Q", Command::Quit),
+    ("Ctrl-S", Command::Save),
+    ("Ctrl-F", Command::Search),
+    ("Ctrl-R", Command::Replace),
+    ("Ctrl-E", Command::ConvertLineEnding),
+    ("Ctrl-N", Command::NextBuffer),
+    ("Ctrl-P", Command::PreviousBuffer),
+    ("Ctrl-O", Command::Open),
+    ("Ctrl-W", Command::CloseBuffer),
+    ("Ctrl-B", Command::PickBuffer),
+    ("Alt-s", Command::SplitStacked),
+    ("Alt-v", Command::SplitSideBySide),
+    ("Alt-q", Command::ClosePane),
+    ("Alt-w", Command::NextPane),
+    ("Alt-l", Command::ToggleLineNumbers),
+    ("Alt-z", Command::ToggleWrap),
+    ("Ctrl-Z", Command::Undo),
+    ("Ctrl-Y", Command::Redo),
+    ("Ctrl-Space", Command::ToggleMark),
+    ("Esc", Command::ClearSelection),
+    ("Delete", Command::DeleteForward),
+    ("Backspace", Command::DeleteBackward),
+    ("Up", Command::MoveUp),
+    ("Down", Command::MoveDown),
+    ("Left", Command::MoveLeft),
+    ("Right", Command::MoveRight),
+    ("PageUp", Command::PageUp),
+    ("PageDown", Command::PageDown),
+    ("Home", Command::LineStart),
+    ("End", Command::LineEnd),
+    ("Alt-f", Command::WordForward),
+    ("Alt-b", Command::WordBackward),
+    ("Ctrl-K", Command::KillLine),
+    ("Alt-k", Command::KillRegion),
+    ("Alt-c", Command::CopyRegion),
+    ("Ctrl-U", Command::Yank),
+    ("Alt-y", Command::YankPop),
+    ("Alt-x", Command::Palette),
+    ("Ctrl-G", Command::Goto),
+];
+
+const EMACS_BINDINGS: [(&str, Command); 50] = [
+    ("Ctrl-A", Command::LineStart),
+    ("Ctrl-E", Command::LineEnd),
+    ("Ctrl-N", Command::MoveDown),
+    ("Ctrl-P", Command::MoveUp),
+    ("Ctrl-F", Command::MoveRight),
+    ("Ctrl-B", Command::MoveLeft),
+    ("Alt-f", Command::WordForward),
+    ("Alt-b", Command::WordBackward),
+    ("Ctrl-V", Command::PageDown),
+    ("Alt-v", Command::PageUp),
+    ("Ctrl-D", Command::DeleteForward),
+    ("Ctrl-K", Command::KillLine),
+    ("Ctrl-W", Command::KillRegion),
+    ("Alt-w", Command::CopyRegion),
+    ("Ctrl-Y", Command::Yank),
+    ("Alt-y", Command::YankPop),
+    ("Ctrl-Space", Command::ToggleMark),
+    ("Ctrl-G", Command::ClearSelection),
+    ("Esc", Command::ClearSelection),
+    ("Ctrl-S", Command::Search),
+    ("Alt-%", Command::Replace),
+    ("Alt-x", Command::Palette),
+    ("Alt-l", Command::ToggleLineNumbers),
+    ("Alt-z", Command::ToggleWrap),
+    ("Alt-g g", Command::Goto),
+    ("Alt-g Alt-g", Command::Goto),
+    ("Ctrl-X Ctrl-S", Command::Save),
+    ("Ctrl-X Ctrl-C", Command::Quit),
+    ("Ctrl-X Ctrl-F", Command::Open),
+    ("Ctrl-X Enter", Command::ConvertLineEnding),
+    ("Ctrl-X b", Command::PickBuffer),
+    ("Ctrl-X k", Command::CloseBuffer),
+    ("Ctrl-X Right", Command::NextBuffer),
+    ("Ctrl-X Left", Command::PreviousBuffer),
+    ("Ctrl-X 2", Command::SplitStacked),
+    ("Ctrl-X 3", Command::SplitSideBySide),
+    ("Ctrl-X 0", Command::ClosePane),
+    ("Ctrl-X o", Command::NextPane),
+    ("Ctrl-X u", Command::Undo),
+    ("Ctrl-X U", Command::Redo),
+    ("Delete", Command::DeleteForward),
+    ("Backspace", Command::DeleteBackward),
+    ("Up", Command::MoveUp),
+    ("Down", Command::MoveDown),
+    ("Left", Command::MoveLeft),
+    ("Right", Command::MoveRight),
+    ("PageUp", Command::PageUp),
+    ("PageDown", Command::PageDown),
+    ("Home", Command::LineStart),
+    ("End", Command::LineEnd),
+];
+
+impl Command {
+    pub const ALL: [Command; 42] = [
+        Command::Copy,
+        Command::Cut,
+        Command::Paste,
+        Command::Quit,
+        Command::Save,
+        Command::Search,
+        Command::Replace,
+        Command::ConvertLineEnding,
+        Command::NextBuffer,
+        Command::PreviousBuffer,
+        Command::Open,
+        Command::CloseBuffer,
+        Command::PickBuffer,
+        Command::SplitStacked,
+        Command::SplitSideBySide,
+        Command::ClosePane,
+        Command::NextPane,
+        Command::ToggleLineNumbers,
+        Command::ToggleWrap,
+        Command::Undo,
+        Command::Redo,
+        Command::ToggleMark,
+        Command::ClearSelection,
+        Command::DeleteForward,
+        Command::DeleteBackward,
+        Command::MoveUp,
+        Command::MoveDown,
+        Command::MoveLeft,
+        Command::MoveRight,
+        Command::PageUp,
+        Command::PageDown,
+        Command::LineStart,
+        Command::LineEnd,
+        Command::Palette,
+        Command::WordForward,
+        Command::WordBackward,
+        Command::KillLine,
+        Command::KillRegion,
+        Command::CopyRegion,
+        Command::Yank,
+        Command::YankPop,
+        Command::Goto,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Command::Copy => "copy",
+            Command::Cut => "cut",
+            Command::Paste => "paste",
+            Command::Quit => "quit",
+            Command::Save => "save",
+            Command::Search => "search",
+            Command::Replace => "replace",
+            Command::ConvertLineEnding => "convert-line-ending",
+            Command::NextBuffer => "next-buffer",
+            Command::PreviousBuffer => "previous-buffer",
+            Command::Open => "open",
+            Command::CloseBuffer => "close-buffer",
+            Command::PickBuffer => "pick-buffer",
+            Command::SplitStacked => "split-stacked",
+            Command::SplitSideBySide => "split-side-by-side",
+            Command::ClosePane => "close-pane",
+            Command::NextPane => "next-pane",
+            Command::ToggleLineNumbers => "toggle-line-numbers",
+            Command::ToggleWrap => "toggle-wrap",
+            Command::Undo => "undo",
+            Command::Redo => "redo",
+            Command::ToggleMark => "toggle-mark",
+            Command::ClearSelection => "clear-selection",
+            Command::DeleteForward => "delete-forward",
+            Command::DeleteBackward => "delete-backward",
+            Command::MoveUp => "move-up",
+            Command::MoveDown => "move-down",
+            Command::MoveLeft => "move-left",
+            Command::MoveRight => "move-right",
+            Command::PageUp => "page-up",
+            Command::PageDown => "page-down",
+            Command::LineStart => "line-start",
+            Command::LineEnd => "line-end",
+            Command::Palette => "palette",
+            Command::WordForward => "word-forward",
+            Command::WordBackward => "word-backward",
+            Command::KillLine => "kill-line",
+            Command::KillRegion => "kill-region",
+            Command::CopyRegion => "copy-region",
+            Command::Yank => "yank",
+            Command::YankPop => "yank-pop",
+            Command::Goto => "goto",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|command| command.name() == name)
+    }
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        Self::preset(Preset::Default)
+    }
+}
+
+impl Keymap {
+    pub fn preset(preset: Preset) -> Self {
+        let table: &[(&str, Command)] = match preset {
+            Preset::Default => &DEFAULT_BINDINGS,
+            Preset::Emacs => &EMACS_BINDINGS,
+        };
+        let bindings = table
+            .iter()
+            .filter_map(|(keys, command)| Some((parse_keys(keys)?, *command)))
+            .collect();
+
+        Self { bindings }
+    }
+
+    /// Applies `overrides` from the config file on top of the `preset` keymap.
+    ///
+    /// Each override maps keys like `"Ctrl-X Ctrl-S"` to a command name, or
+    /// to `"none"` to unbind them. Returns the keymap along with a
+    /// description of every override that was ignored and every binding
+    /// made unreachable by a longer chord.
+    pub fn new(preset: Preset, overrides: &BTreeMap<String, String>) -> (Self, Vec<String>) {
+        let mut keymap = Self::preset(preset);
+        let mut problems = Vec::new();
+
+        for (spec, name) in overrides {
+            let Some(keys) = parse_keys(spec) else {
+                problems.push(format!("unknown key `{spec}`"));
+                continue;
+            };
+
+            if name == "none" {
+                keymap.bindings.remove(&keys);
+            } else if let Some(command) = Command::from_name(name) {
+                keymap.bindings.insert(keys, command);
+            } else {
+                problems.push(format!("unknown command `{name}` for {}", describe(&keys)));
+            }
+        }
+
+        let mut shadowed = keymap
+            .bindings
+            .keys()
+            .filter_map(|keys| {
+                let chord = keymap
+                    .bindings
+                    .keys()
+                    .filter(|other| other.len() > keys.len() && other.starts_with(keys))
+                    .min_by_key(|chord| describe(chord))?;
+
+                Some((keys.clone(), chord.clone()))
+            })
+            .collect::<Vec<_>>();
+
+        shadowed.sort_by_key(|(keys, _)| describe(keys));
+
+        for (keys, chord) in shadowed {
+            if let Some(command) = keymap.bindings.remove(&keys) {
+                problems.push(format!(
+                    "{} ({}) conflicts with {}",
+                    describe(&keys),
+                    command.name(),
+                    describe(&chord)
+                ));
+            }
+        }
+
+        if keymap.binding(Command::Quit).is_none() {
+            problems.push("quit is not bound to any key".to_owned());
+        }
+
+        (keymap, problems)
+    }
+
+    pub fn lookup(&self, keys: &[Key]) -> Lookup {
+        if let Some(command) = self.bindings.get(keys) {
+            return Lookup::Command(*command);
+        }
+
+        if self
+            .bindings
+            .keys()
+            .any(|bound| bound.len() > keys.len() && bound.starts_with(keys))
+        {
+            return Lookup::Prefix;
+        }
+
+        Lookup::Unbound
+    }
+
+    /// Returns the shortest keys bound to `command`, for help messages.
+    pub fn binding(&self, command: Command) -> Option<String> {
+        self.bindings
+            .iter()
+            .filter(|(_, bound)| **bound == command)
+            .map(|(keys, _)| keys)
+            .min_by_key(|keys| (keys.len(), describe(keys)))
+            .map(|keys| describe(keys))
+    }
+}
+
+/// Parses space separated keys such as `Ctrl-X Ctrl-S`, `Alt-w`, `PageUp`
+/// or `a`. `C-` and `M-` are accepted for Ctrl and Alt.
+pub fn parse_keys(spec: &str) -> Option<Vec<Key>> {
+    let keys = spec
+        .split_whitespace()
+        .map(parse_key)
+        .collect::<Option<Vec<_>>>()?;
+
+    if keys.is_empty() {
+        return None;
+    }
+
+    Some(keys)
+}
+
+fn parse_key(spec: &str) -> Option<Key> {
+    let single = |name: &str| {
+        let mut chars = name.chars();
+
+        match (chars.next(), chars.next()) {
+            (Some(c), None) => Some(c),
+            _ => None,
+        }
+    };
+
+    if let Some(name) = strip_modifier(spec, &["Ctrl-", "C-"]) {
+        if name.eq_ignore_ascii_case("space") || name == "@" {
+            return Some(Key::Null);
+        }
+
+        // The terminal sends these as Tab and Enter, and Ctrl-H as what many
+        // terminals send for Backspace.
+        return match single(name)?.to_ascii_lowercase() {
+            'i' => Some(Key::Char('\t')),
+            'j' | 'm' => Some(Key::Char('\n')),
+            'h' => None,
+            c if c.is_ascii_alphanumeric() => Some(Key::Ctrl(c)),
+            _ => None,
+        };
+    }
+
+    if let Some(name) = strip_modifier(spec, &["Alt-", "M-"]) {
+        return single(name).map(Key::Alt);
+    }
+
+    let key = match spec.to_ascii_lowercase().as_str() {
+        "up" => Key::Up,
+        "down" => Key::Down,
+        "left" => Key::Left,
+        "right" => Key::Right,
+        "pageup" => Key::PageUp,
+        "pagedown" => Key::PageDown,
+        "home" => Key::Home,
+        "end" => Key::End,
+        "insert" => Key::Insert,
+        "delete" => Key::Delete,
+        "backspace" => Key::Backspace,
+        "esc" => Key::Esc,
+        "tab" => Key::Char('\t'),
+        "backtab" => Key::BackTab,
+        "enter" => Key::Char('\n'),
+        "space" => Key::Char(' '),
+        name => match name.strip_prefix('f').map(str::parse) {
+            Some(Ok(n @ 1..=12)) => Key::F(n),
+            _ => Key::Char(single(spec)?),
+        },
+    };
+
+    Some(key)
+}
+
+fn strip_modifier<'a>(spec: &'a str, prefixes: &[&str]) -> Option<&'a str> {
+    prefixes.iter().find_map(|prefix| {
+        let head = spec.get(..prefix.len())?;
+
+        head.eq_ignore_ascii_case(prefix)
+            .then(|| &spec[prefix.len()..])
+    })
+}
+
+/// Writes keys the way `parse_keys` reads them, e.g. `Ctrl-X Ctrl-S`.
+pub fn describe(keys: &[Key]) -> String {
+    keys.iter().map(key_name).collect::<Vec<_>>().join(" ")
+}
+
+fn key_name(key: &Key) -> String {
+    match key {
+        Key::Ctrl(c) => format!("Ctrl-{}", c.to_ascii_uppercase()),
+        Key::Alt(c) => format!("Alt-{c}"),
+        Key::Null => "Ctrl-Space".to_owned(),
+        Key::Char('\t') => "Tab".to_owned(),
+        Key::Char('\n') => "Enter".to_owned(),
+        Key::Char(' ') => "Space".to_owned(),
+        Key::Char(c) => c.to_string(),
+        Key::F(n) => format!("F{n}"),
+        Key::PageUp => "PageUp".to_owned(),
+        Key::PageDown => "PageDown".to_owned(),
+        Key::BackTab => "BackTab".to_owned(),
+        key => format!("{key:?}"),
+    }
+}
+
+#[cfg(test)]
+mod keymap_tests {
+    use super::*;
+
+    fn overrides(pairs: &[(&str, &str)]) -> BTreeMap<String, String> {
+        pairs
+            .iter()
+            .map(|(keys, command)| ((*keys).to_owned(), (*command).to_owned()))
+            .collect()
+    }
+
+    #[test]
+    fn test_parse_and_describe_keys() {
+        let keys = parse_keys("C-x ctrl-S M-w PageUp F5 Tab a").unwrap();
+
+        assert_eq!(
+            keys,
+            [
+                Key::Ctrl('x'),
+                Key::Ctrl('s'),
+                Key::Alt('w'),
+                Key::PageUp,
+                Key::F(5),
+                Key::Char('\t'),
+                Key::Char('a')
+            ]
+        );
+        assert_eq!(describe(&keys), "Ctrl-X Ctrl-S Alt-w PageUp F5 Tab a");
+        assert_eq!(parse_keys(&describe(&keys)), Some(keys));
+        assert_eq!(parse_keys("Ctrl-Space"), Some(vec![Key::Null]));
+        assert_eq!(
+            parse_keys("Ctrl-I C-m Ctrl-J"),
+            parse_keys("Tab Enter Enter")
+        );
+        assert_eq!(parse_keys("Ctrl-H"), None);
+        assert_eq!(parse_keys("Hyper-x"), None);
+        assert_eq!(parse_keys(""), None);
+    }
+
+    #[test]
+    fn test_default_keymap() {
+        let keymap = Keymap::default();
+
+        assert_eq!(
+            keymap.lookup(&[Key::Ctrl('s')]),
+            Lookup::Command(Command::Save)
+        );
+        assert_eq!(keymap.lookup(&[Key::Char('a')]), Lookup::Unbound);
+        assert_eq!(keymap.binding(Command::Quit).as_deref(), Some("Ctrl-Q"));
+
+        for command in Command::ALL {
+            assert_eq!(Command::from_name(command.name()), Some(command));
+            assert!(keymap.binding(command).is_some(), "{}", command.name());
+        }
+    }
+
+    #[test]
+    fn test_emacs_keymap() {
+        let (keymap, problems) = Keymap::new(Preset::Emacs, &BTreeMap::new());
+
+        assert!(problems.is_empty(), "{problems:?}");
+
+        for command in Command::ALL {
+            let clipboard = [Command::Copy, Command::Cut, Command::Paste];
+
+            assert!(
+                keymap.binding(command).is_some() || clipboard.contains(&command),
+                "{}",
+                command.name()
+            );
+        }
+
+        assert_eq!(
+            keymap.lookup(&[Key::Ctrl('k')]),
+            Lookup::Command(Command::KillLine)
+        );
+        assert_eq!(
+            keymap.lookup(&[Key::Ctrl('x'), Key::Char('2')]),
+            Lookup::Command(Command::SplitStacked)
+        );
+        assert_eq!(keymap.lookup(&[Key::Ctrl('x')]), Lookup::Prefix);
+        assert_eq!(
+            keymap.binding(Command::Quit).as_deref(),
+            Some("Ctrl-X Ctrl-C")
+        );
+    }
+
+    #[test]
+    fn test_unbound_quit() {
+        let (keymap, problems) = Keymap::new(Preset::Default, &overrides(&[("Ctrl-Q", "none")]));
+
+        assert_eq!(keymap.binding(Command::Quit), None);
+        assert_eq!(problems, ["quit is not bound to any key"]);
+    }
+
+    #[test]
+    fn test_chords_and_overrides() {
+        let (keymap, problems) = Keymap::new(
+            Preset::Default,
+            &overrides(&[
+                ("Ctrl-X Ctrl-S", "save"),
+                ("Ctrl-X Ctrl-C", "quit"),
+                ("Ctrl-Q", "none"),
+                ("Alt-k", "cut"),
+                ("Ctrl-G", "sav"),
+                ("Super-x", "save"),
+            ]),
+        );
+
+        assert_eq!(keymap.lookup(&[Key::Ctrl('x')]), Lookup::Prefix);
+        assert_eq!(
+            keymap.lookup(&[Key::Ctrl('x'), Key::Ctrl('s')]),
+            Lookup::Command(Command::Save)
+        );
+        assert_eq!(
+            keymap.lookup(&[Key::Ctrl('x'), Key::Ctrl('q')]),
+            Lookup::Unbound
+        );
+        assert_eq!(keymap.lookup(&[Key::Ctrl('q')]), Lookup::Unbound);
+        assert_eq!(
+            keymap.lookup(&[Key::Alt('k')]),
+            Lookup::Command(Command::Cut)
+        );
+        assert_eq!(
+            keymap.binding(Command::Quit).as_deref(),
+            Some("Ctrl-X Ctrl-C")
+        );
+        assert_eq!(
+            problems,
+            [
+                "unknown command `sav` for Ctrl-G",
+                "unknown key `Super-x`",
+                "Ctrl-X (cut) conflicts with Ctrl-X Ctrl-C",
+            ]
+        );
+    }
+}
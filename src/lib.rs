@@ -0,0 +1,39 @@
+#![warn(clippy::all)]
+mod application;
+mod buffer;
+mod command_line;
+mod config;
+mod diff;
+mod document;
+mod emacs;
+mod filetype;
+mod goto;
+mod highlight;
+mod history;
+mod keymap;
+mod keywords;
+mod line_ending;
+mod pane;
+mod prompt;
+mod query;
+mod rope;
+mod row;
+mod swap;
+mod terminal;
+mod vi;
+
+pub use application::Position;
+pub use application::Revise;
+pub use application::SearchDirection;
+pub use buffer::Buffer;
+pub use config::Config;
+pub use config::Theme;
+pub use document::Document;
+pub use filetype::FileType;
+pub use filetype::HighlightOptions;
+pub use line_ending::LineEnding;
+pub use query::Pattern;
+pub use query::Query;
+pub use rope::Rope;
+pub use row::Row;
+pub use terminal::Terminal;
@@ -0,0 +1,159 @@
+use std::fmt;
+use std::str::FromStr;
+use thiserror::Error;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum LineEnding {
+    #[default]
+    Lf,
+    CrLf,
+    Cr,
+}
+
+#[derive(Debug, Error)]
+#[error("unknown line ending: {0} (expected lf, crlf or cr)")]
+pub struct ParseLineEndingError(String);
+
+impl LineEnding {
+    /// Picks the style most line terminators in `contents` have, the first
+    /// one found on a tie.
+    pub fn detect(contents: &str) -> Self {
+        let mut counts = [0; 3];
+        let mut first = None;
+
+        for ending in terminators(contents) {
+            first.get_or_insert(ending);
+            counts[ending as usize] += 1;
+        }
+
+        let Some(first) = first else {
+            return LineEnding::default();
+        };
+
+        [LineEnding::Lf, LineEnding::CrLf, LineEnding::Cr]
+            .into_iter()
+            .filter(|ending| counts[*ending as usize] > counts[first as usize])
+            .max_by_key(|ending| counts[*ending as usize])
+            .unwrap_or(first)
+    }
+
+    /// Tells whether `contents` uses more than one style of line terminator.
+    pub fn is_mixed(contents: &str) -> bool {
+        let mut terminators = terminators(contents);
+        let Some(first) = terminators.next() else {
+            return false;
+        };
+
+        terminators.any(|ending| ending != first)
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            LineEnding::Lf => "\n",
+            LineEnding::CrLf => "\r\n",
+            LineEnding::Cr => "\r",
+        }
+    }
+
+    /// Splits `contents` into lines at any of `\r\n`, `\r` or `\n` and tells
+    /// whether it ended with a terminator.
+    pub fn split(contents: &str) -> (Vec<&str>, bool) {
+        let mut lines = Vec::new();
+        let mut start = 0;
+        let bytes = contents.as_bytes();
+        let mut index = 0;
+
+        while index < bytes.len() {
+            match bytes[index] {
+                b'\r' | b'\n' => {
+                    lines.push(&contents[start..index]);
+
+                    if bytes[index..].starts_with(b"\r\n") {
+                        index += 1;
+                    }
+
+                    start = index + 1;
+                }
+                _ => (),
+            }
+
+            index += 1;
+        }
+
+        let trailing_newline = !contents.is_empty() && start == contents.len();
+
+        if !trailing_newline && !contents.is_empty() {
+            lines.push(&contents[start..]);
+        }
+
+        (lines, trailing_newline)
+    }
+}
+
+/// The style of every line terminator in `contents`, in order.
+fn terminators(contents: &str) -> impl Iterator<Item = LineEnding> + '_ {
+    let bytes = contents.as_bytes();
+
+    bytes
+        .iter()
+        .enumerate()
+        .filter_map(move |(index, byte)| match byte {
+            b'\r' if bytes.get(index + 1) == Some(&b'\n') => Some(LineEnding::CrLf),
+            b'\r' => Some(LineEnding::Cr),
+            b'\n' if index > 0 && bytes[index - 1] == b'\r' => None,
+            b'\n' => Some(LineEnding::Lf),
+            _ => None,
+        })
+}
+
+impl fmt::Display for LineEnding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LineEnding::Lf => write!(f, "LF"),
+            LineEnding::CrLf => write!(f, "CRLF"),
+            LineEnding::Cr => write!(f, "CR"),
+        }
+    }
+}
+
+impl FromStr for LineEnding {
+    type Err = ParseLineEndingError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "lf" | "unix" => Ok(LineEnding::Lf),
+            "crlf" | "dos" | "windows" => Ok(LineEnding::CrLf),
+            "cr" | "mac" => Ok(LineEnding::Cr),
+            _ => Err(ParseLineEndingError(s.to_owned())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod line_ending_tests {
+    use super::*;
+
+    #[test]
+    fn test_detect() {
+        assert_eq!(LineEnding::detect("a\r\nb\r\n"), LineEnding::CrLf);
+        assert_eq!(LineEnding::detect("a\rb"), LineEnding::Cr);
+        assert_eq!(LineEnding::detect("a\nb\r\n"), LineEnding::Lf);
+        assert_eq!(LineEnding::detect("a\nb\r\nc\r\n"), LineEnding::CrLf);
+        assert_eq!(LineEnding::detect("a"), LineEnding::Lf);
+        assert!(LineEnding::is_mixed("a\r\nb\nc"));
+        assert!(!LineEnding::is_mixed("a\r\nb\r\n"));
+        assert!(!LineEnding::is_mixed("a"));
+    }
+
+    #[test]
+    fn test_split() {
+        assert_eq!(LineEnding::split("a\r\nb\r\n"), (vec!["a", "b"], true));
+        assert_eq!(LineEnding::split("a\n\nb"), (vec!["a", "", "b"], false));
+        assert_eq!(
+            LineEnding::split("a\r\nb\nc\rd\r\n"),
+            (vec!["a", "b", "c", "d"], true)
+        );
+        assert_eq!(LineEnding::split("\n"), (vec![""], true));
+        assert_eq!(LineEnding::split(""), (vec![], false));
+    }
+}
@@ -0,0 +1,344 @@
+use crate::Document;
+use crate::Position;
+use crate::Row;
+
+/// A view onto a buffer, with its own cursor and scroll offset.
+#[derive(Clone, Default)]
+pub struct Pane {
+    pub buffer: usize,
+    pub cursor_position: Position,
+    pub offset: Position,
+}
+
+impl Pane {
+    /// Follows an edit that added `delta` rows after row `y`, or joined
+    /// `-delta` rows after it into it.
+    pub fn shift_rows(&mut self, y: usize, delta: isize) {
+        for row in [&mut self.cursor_position.y, &mut self.offset.y] {
+            if *row <= y {
+                continue;
+            }
+
+            *row = match delta.checked_neg() {
+                Some(joined) if joined > 0 => row.saturating_sub(joined as usize).max(y),
+                _ => *row + delta as usize,
+            };
+        }
+    }
+
+    /// Keeps the cursor within `document`, and the offset above it.
+    pub fn clamp(&mut self, document: &Document) {
+        let y = self.cursor_position.y.min(document.len());
+
+        self.cursor_position.y = y;
+        self.cursor_position.x = self
+            .cursor_position
+            .x
+            .min(document.row(y).map_or(0, Row::len));
+        self.offset.y = self.offset.y.min(y);
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SplitDirection {
+    /// Panes stacked on top of each other.
+    Horizontal,
+    /// Panes side by side, separated by a column.
+    Vertical,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Rect {
+    pub x: u16,
+    pub y: u16,
+    pub width: u16,
+    pub height: u16,
+}
+
+/// How the screen is divided between panes, referenced by index.
+pub enum Layout {
+    Pane(usize),
+    Split {
+        direction: SplitDirection,
+        first: Box<Layout>,
+        second: Box<Layout>,
+    },
+}
+
+impl Default for Layout {
+    fn default() -> Self {
+        Layout::Pane(0)
+    }
+}
+
+impl Layout {
+    /// Splits `pane` in two, putting `new_pane` after it.
+    pub fn split(&mut self, pane: usize, new_pane: usize, direction: SplitDirection) -> bool {
+        match self {
+            Layout::Pane(index) if *index == pane => {
+                *self = Layout::Split {
+                    direction,
+                    first: Box::new(Layout::Pane(pane)),
+                    second: Box::new(Layout::Pane(new_pane)),
+                };
+
+                true
+            }
+            Layout::Pane(_) => false,
+            Layout::Split { first, second, .. } => {
+                first.split(pane, new_pane, direction) || second.split(pane, new_pane, direction)
+            }
+        }
+    }
+
+    /// Removes `pane`, giving its space to its sibling, and shifts the
+    /// indices of the panes after it down by one.
+    pub fn remove(&mut self, pane: usize) -> bool {
+        let removed = self.remove_leaf(pane);
+
+        if removed {
+            self.renumber(pane);
+        }
+
+        removed
+    }
+
+    /// Lists every pane with the area it covers in `area`.
+    pub fn rects(&self, area: Rect) -> Vec<(usize, Rect)> {
+        let mut rects = Vec::new();
+
+        self.collect_rects(area, &mut rects, &mut Vec::new());
+
+        rects
+    }
+
+    /// Lists the single-column areas drawn between side by side panes.
+    pub fn separators(&self, area: Rect) -> Vec<Rect> {
+        let mut separators = Vec::new();
+
+        self.collect_rects(area, &mut Vec::new(), &mut separators);
+
+        separators
+    }
+
+    fn remove_leaf(&mut self, pane: usize) -> bool {
+        if let Layout::Split { first, second, .. } = self {
+            let sibling = match (&**first, &**second) {
+                (Layout::Pane(index), _) if *index == pane => Some(std::mem::take(&mut **second)),
+                (_, Layout::Pane(index)) if *index == pane => Some(std::mem::take(&mut **first)),
+                _ => None,
+            };
+
+            return match sibling {
+                Some(sibling) => {
+                    *self = sibling;
+                    true
+                }
+                None => first.remove_leaf(pane) || second.remove_leaf(pane),
+            };
+        }
+
+        false
+    }
+
+    fn renumber(&mut self, removed: usize) {
+        match self {
+            Layout::Pane(index) => {
+                if *index > removed {
+                    *index -= 1;
+                }
+            }
+            Layout::Split { first, second, .. } => {
+                first.renumber(removed);
+                second.renumber(removed);
+            }
+        }
+    }
+
+    fn collect_rects(
+        &self,
+        area: Rect,
+        rects: &mut Vec<(usize, Rect)>,
+        separators: &mut Vec<Rect>,
+    ) {
+        match self {
+            Layout::Pane(index) => rects.push((*index, area)),
+            Layout::Split {
+                direction: SplitDirection::Horizontal,
+                first,
+                second,
+            } => {
+                let height = area.height / 2;
+
+                first.collect_rects(Rect { height, ..area }, rects, separators);
+                second.collect_rects(
+                    Rect {
+                        y: area.y + height,
+                        height: area.height - height,
+                        ..area
+                    },
+                    rects,
+                    separators,
+                );
+            }
+            Layout::Split {
+                direction: SplitDirection::Vertical,
+                first,
+                second,
+            } => {
+                let width = area.width.saturating_sub(1) / 2;
+                let separator = Rect {
+                    x: area.x + width,
+                    width: area.width.min(1),
+                    ..area
+                };
+
+                first.collect_rects(Rect { width, ..area }, rects, separators);
+                separators.push(separator);
+                second.collect_rects(
+                    Rect {
+                        x: separator.x + separator.width,
+                        width: area.width - width - separator.width,
+                        ..area
+                    },
+                    rects,
+                    separators,
+                );
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod pane_tests {
+    use super::*;
+
+    const AREA: Rect = Rect {
+        x: 0,
+        y: 0,
+        width: 81,
+        height: 24,
+    };
+
+    #[test]
+    fn test_split_rects() {
+        let mut layout = Layout::default();
+
+        assert!(layout.split(0, 1, SplitDirection::Vertical));
+        assert!(layout.split(1, 2, SplitDirection::Horizontal));
+        assert!(!layout.split(5, 6, SplitDirection::Horizontal));
+
+        assert_eq!(
+            layout.rects(AREA),
+            vec![
+                (
+                    0,
+                    Rect {
+                        x: 0,
+                        y: 0,
+                        width: 40,
+                        height: 24
+                    }
+                ),
+                (
+                    1,
+                    Rect {
+                        x: 41,
+                        y: 0,
+                        width: 40,
+                        height: 12
+                    }
+                ),
+                (
+                    2,
+                    Rect {
+                        x: 41,
+                        y: 12,
+                        width: 40,
+                        height: 12
+                    }
+                ),
+            ]
+        );
+        assert_eq!(
+            layout.separators(AREA),
+            vec![Rect {
+                x: 40,
+                y: 0,
+                width: 1,
+                height: 24
+            }]
+        );
+    }
+
+    #[test]
+    fn test_remove_gives_space_to_sibling() {
+        let mut layout = Layout::default();
+
+        layout.split(0, 1, SplitDirection::Vertical);
+        layout.split(1, 2, SplitDirection::Horizontal);
+
+        assert!(layout.remove(1));
+        assert_eq!(
+            layout.rects(AREA),
+            vec![
+                (
+                    0,
+                    Rect {
+                        x: 0,
+                        y: 0,
+                        width: 40,
+                        height: 24
+                    }
+                ),
+                (
+                    1,
+                    Rect {
+                        x: 41,
+                        y: 0,
+                        width: 40,
+                        height: 24
+                    }
+                ),
+            ]
+        );
+
+        assert!(layout.remove(0));
+        assert_eq!(layout.rects(AREA), vec![(0, AREA)]);
+        assert!(!layout.remove(0));
+    }
+
+    #[test]
+    fn test_follow_edits() {
+        let mut document = Document::default();
+
+        document.insert_str(&Position::default(), "a\nb\nc\nd\ne");
+        document.take_row_shifts();
+
+        let mut pane = Pane {
+            buffer: 0,
+            cursor_position: Position { x: 1, y: 3 },
+            offset: Position { x: 0, y: 2 },
+        };
+
+        document.insert_str(&Position { x: 0, y: 1 }, "x\ny\n");
+
+        for (y, delta) in document.take_row_shifts() {
+            pane.shift_rows(y, delta);
+        }
+
+        assert_eq!(pane.cursor_position, Position { x: 1, y: 5 });
+        assert_eq!(pane.offset.y, 4);
+        assert_eq!(document.row(5).unwrap().as_string(), "d");
+
+        document.delete_range(&Position { x: 0, y: 2 }, &Position { x: 0, y: 7 });
+
+        for (y, delta) in document.take_row_shifts() {
+            pane.shift_rows(y, delta);
+        }
+
+        pane.clamp(&document);
+        assert_eq!(pane.cursor_position, Position { x: 0, y: 2 });
+        assert_eq!(pane.offset.y, 2);
+    }
+}
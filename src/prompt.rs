@@ -0,0 +1,302 @@
+use crate::config;
+
+use std::collections::HashMap;
+use std::fs;
+use std::io::Error as IOError;
+use std::path::{Path, PathBuf};
+use std::process;
+
+const HISTORY_LEN: usize = 100;
+
+/// Prompts that remember what was typed in them.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum PromptKind {
+    Search,
+    Replace,
+    /// File names, for save as and open.
+    File,
+    Goto,
+    Command,
+}
+
+/// Answers previously given to each kind of prompt, oldest first.
+///
+/// History is kept in `$XDG_STATE_HOME/revise/history` (default
+/// `~/.local/state/revise/history`), one entry per line prefixed with the
+/// prompt kind and a tab.
+#[derive(Default)]
+pub struct PromptHistory {
+    entries: HashMap<PromptKind, Vec<String>>,
+    path: Option<PathBuf>,
+}
+
+/// The text of a prompt being edited, with a cursor counted in chars.
+#[derive(Default)]
+pub struct LineEditor {
+    text: String,
+    cursor: usize,
+}
+
+impl PromptKind {
+    fn name(self) -> &'static str {
+        match self {
+            PromptKind::Search => "search",
+            PromptKind::Replace => "replace",
+            PromptKind::File => "file",
+            PromptKind::Goto => "goto",
+            PromptKind::Command => "command",
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Self> {
+        [
+            PromptKind::Search,
+            PromptKind::Replace,
+            PromptKind::File,
+            PromptKind::Goto,
+            PromptKind::Command,
+        ]
+        .into_iter()
+        .find(|kind| kind.name() == name)
+    }
+}
+
+impl PromptHistory {
+    /// Reads the history file, starting empty if there is none.
+    pub fn load() -> Self {
+        match config::state_dir() {
+            Some(dir) => Self::open(dir.join("history")),
+            None => Self::default(),
+        }
+    }
+
+    pub fn open(path: PathBuf) -> Self {
+        Self {
+            entries: read(&path).unwrap_or_default(),
+            path: Some(path),
+        }
+    }
+
+    pub fn entries(&self, kind: PromptKind) -> &[String] {
+        self.entries.get(&kind).map_or(&[], Vec::as_slice)
+    }
+
+    /// Records `entry` as the latest answer to `kind`, dropping older copies.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Error` if the history file cannot be written
+    pub fn push(&mut self, kind: PromptKind, entry: &str) -> Result<(), IOError> {
+        if entry.is_empty() {
+            return Ok(());
+        }
+
+        // Other sessions may have added entries since the file was read.
+        if let Some(entries) = self.path.as_deref().and_then(|path| read(path).ok()) {
+            self.entries = entries;
+        }
+
+        let entries = self.entries.entry(kind).or_default();
+
+        entries.retain(|existing| existing != entry);
+        entries.push(entry.to_owned());
+
+        if entries.len() > HISTORY_LEN {
+            entries.drain(..entries.len() - HISTORY_LEN);
+        }
+
+        self.save()
+    }
+
+    fn save(&self) -> Result<(), IOError> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        let mut contents = String::new();
+
+        for (kind, entries) in &self.entries {
+            for entry in entries {
+                contents.push_str(&format!("{}\t{entry}\n", kind.name()));
+            }
+        }
+
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        // Written aside and renamed over the file, so a crash never leaves it
+        // half written.
+        let temp_path = path.with_extension(format!("{}.tmp", process::id()));
+        let written = fs::write(&temp_path, contents).and_then(|()| fs::rename(&temp_path, path));
+
+        if written.is_err() {
+            let _ = fs::remove_file(&temp_path);
+        }
+
+        written
+    }
+}
+
+fn read(path: &Path) -> Result<HashMap<PromptKind, Vec<String>>, IOError> {
+    let mut entries: HashMap<PromptKind, Vec<String>> = HashMap::new();
+
+    for line in fs::read_to_string(path)?.lines() {
+        let kind = line
+            .split_once('\t')
+            .and_then(|(kind, entry)| Some((PromptKind::from_name(kind)?, entry)));
+
+        if let Some((kind, entry)) = kind {
+            entries.entry(kind).or_default().push(entry.to_owned());
+        }
+    }
+
+    Ok(entries)
+}
+
+impl LineEditor {
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    pub fn cursor(&self) -> usize {
+        self.cursor
+    }
+
+    /// Replaces the text, putting the cursor at its end.
+    pub fn set(&mut self, text: &str) {
+        text.clone_into(&mut self.text);
+        self.cursor = self.text.chars().count();
+    }
+
+    pub fn clear(&mut self) {
+        self.set("");
+    }
+
+    pub fn insert(&mut self, c: char) {
+        let index = self.byte_index(self.cursor);
+
+        self.text.insert(index, c);
+        self.cursor += 1;
+    }
+
+    pub fn backspace(&mut self) {
+        if self.cursor > 0 {
+            self.cursor -= 1;
+            self.delete();
+        }
+    }
+
+    pub fn delete(&mut self) {
+        let index = self.byte_index(self.cursor);
+
+        if index < self.text.len() {
+            self.text.remove(index);
+        }
+    }
+
+    /// Deletes back to the start of the word before the cursor.
+    pub fn delete_word(&mut self) {
+        let chars = self.text.chars().collect::<Vec<_>>();
+        let mut start = self.cursor;
+
+        while start > 0 && chars[start - 1].is_whitespace() {
+            start -= 1;
+        }
+
+        while start > 0 && !chars[start - 1].is_whitespace() {
+            start -= 1;
+        }
+
+        let range = self.byte_index(start)..self.byte_index(self.cursor);
+
+        self.text.replace_range(range, "");
+        self.cursor = start;
+    }
+
+    pub fn left(&mut self) {
+        self.cursor = self.cursor.saturating_sub(1);
+    }
+
+    pub fn right(&mut self) {
+        self.cursor = (self.cursor + 1).min(self.text.chars().count());
+    }
+
+    pub fn home(&mut self) {
+        self.cursor = 0;
+    }
+
+    pub fn end(&mut self) {
+        self.cursor = self.text.chars().count();
+    }
+
+    fn byte_index(&self, cursor: usize) -> usize {
+        self.text
+            .char_indices()
+            .nth(cursor)
+            .map_or(self.text.len(), |(index, _)| index)
+    }
+}
+
+#[cfg(test)]
+mod prompt_tests {
+    use super::*;
+    use std::env;
+
+    #[test]
+    fn test_line_editor() {
+        let mut line = LineEditor::default();
+
+        line.set("git comit");
+        line.left();
+        line.left();
+        line.insert('m');
+        assert_eq!((line.text(), line.cursor()), ("git commit", 8));
+
+        line.end();
+        line.delete_word();
+        assert_eq!(line.text(), "git ");
+
+        line.home();
+        line.delete();
+        line.right();
+        line.backspace();
+        assert_eq!((line.text(), line.cursor()), ("t ", 0));
+    }
+
+    #[test]
+    fn test_history_persists_per_kind() {
+        let path = env::temp_dir().join(format!("revise-prompt-history-{}", process::id()));
+        let _ = fs::remove_file(&path);
+        let mut history = PromptHistory::open(path.clone());
+
+        history.push(PromptKind::Search, "foo").unwrap();
+        history.push(PromptKind::Search, "bar").unwrap();
+        history.push(PromptKind::Search, "foo").unwrap();
+        history.push(PromptKind::File, "notes.md").unwrap();
+
+        let history = PromptHistory::open(path.clone());
+
+        assert_eq!(history.entries(PromptKind::Search), ["bar", "foo"]);
+        assert_eq!(history.entries(PromptKind::File), ["notes.md"]);
+        assert!(history.entries(PromptKind::Goto).is_empty());
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_history_merges_sessions() {
+        let path = env::temp_dir().join(format!("revise-prompt-sessions-{}", process::id()));
+        let _ = fs::remove_file(&path);
+        let mut first = PromptHistory::open(path.clone());
+        let mut second = PromptHistory::open(path.clone());
+
+        first.push(PromptKind::Search, "one").unwrap();
+        second.push(PromptKind::Search, "two").unwrap();
+        first.push(PromptKind::Goto, "12").unwrap();
+
+        let history = PromptHistory::open(path.clone());
+
+        assert_eq!(history.entries(PromptKind::Search), ["one", "two"]);
+        assert_eq!(history.entries(PromptKind::Goto), ["12"]);
+        fs::remove_file(&path).unwrap();
+    }
+}
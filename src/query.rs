@@ -0,0 +1,307 @@
+use regex::{Error as RegexError, Regex, RegexBuilder};
+use regex_syntax::hir::{Class, Hir, HirKind};
+
+/// Most line breaks a match is looked for across, for patterns like `\s+`
+/// that could cross any number of them.
+const MAX_SPANS: usize = 16;
+
+/// A search as typed in the prompt, along with its matching modes.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Query {
+    pub text: String,
+    pub regex: bool,
+    pub ignore_case: bool,
+    pub whole_word: bool,
+}
+
+/// A compiled `Query`, ready to be run against rows.
+#[derive(Clone, Debug)]
+pub struct Pattern {
+    regex: Regex,
+    expand: bool,
+    spans: usize,
+}
+
+impl Query {
+    /// # Errors
+    ///
+    /// Will return `Error` if the query is an invalid regular expression
+    ///
+    /// `\n` in the query matches a line break, in literal queries too, where
+    /// `\\` stands for a backslash.
+    pub fn compile(&self) -> Result<Pattern, RegexError> {
+        let mut source = if self.regex {
+            self.text.clone()
+        } else {
+            regex::escape(&unescape(&self.text))
+        };
+
+        if self.whole_word {
+            source = format!(r"\b(?:{source})\b");
+        }
+
+        let regex = RegexBuilder::new(&source)
+            .case_insensitive(self.ignore_case)
+            .build()?;
+
+        let spans = regex_syntax::parse(&source).map_or(0, |hir| {
+            line_breaks(&hir).map_or(MAX_SPANS, |spans| spans.min(MAX_SPANS))
+        });
+
+        Ok(Pattern {
+            regex,
+            expand: self.regex,
+            spans,
+        })
+    }
+
+    /// Lists the enabled modes, e.g. `[regex, word]`.
+    pub fn modes(&self) -> String {
+        let modes = [
+            (self.regex, "regex"),
+            (self.ignore_case, "ignore case"),
+            (self.whole_word, "word"),
+        ]
+        .iter()
+        .filter(|(enabled, _)| *enabled)
+        .map(|(_, name)| *name)
+        .collect::<Vec<_>>();
+
+        if modes.is_empty() {
+            String::new()
+        } else {
+            format!("[{}]", modes.join(", "))
+        }
+    }
+}
+
+impl Pattern {
+    /// How many line breaks a match can cross.
+    pub fn spans(&self) -> usize {
+        self.spans
+    }
+
+    /// Returns the first non-empty match starting at or after byte `at`.
+    pub fn find_at(&self, haystack: &str, at: usize) -> Option<(usize, usize)> {
+        let mut at = at;
+
+        while at <= haystack.len() {
+            let found = self.regex.find_at(haystack, at)?;
+
+            if !found.is_empty() {
+                return Some((found.start(), found.end()));
+            }
+
+            at = found.end()
+                + haystack[found.end()..]
+                    .chars()
+                    .next()
+                    .map_or(1, char::len_utf8);
+        }
+
+        None
+    }
+
+    /// Returns the last non-empty match starting before byte `before`.
+    pub fn rfind_before(&self, haystack: &str, before: usize) -> Option<(usize, usize)> {
+        let mut last = None;
+        let mut at = 0;
+
+        while let Some((start, end)) = self.find_at(haystack, at) {
+            if start >= before {
+                break;
+            }
+
+            last = Some((start, end));
+            at = end;
+        }
+
+        last
+    }
+
+    /// Builds the text replacing the match starting at byte `at`, expanding
+    /// `$1` and `${name}` references for regex queries.
+    pub fn expand(&self, haystack: &str, at: usize, replacement: &str) -> String {
+        if !self.expand {
+            return replacement.to_owned();
+        }
+
+        let mut expanded = String::new();
+
+        if let Some(captures) = self.regex.captures_at(haystack, at) {
+            captures.expand(replacement, &mut expanded);
+        }
+
+        expanded
+    }
+}
+
+/// Turns `\n` into a line break and `\\` into a backslash, leaving any
+/// other backslash as typed.
+fn unescape(text: &str) -> String {
+    let mut unescaped = String::with_capacity(text.len());
+    let mut chars = text.chars();
+
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+
+        match chars.clone().next() {
+            Some('n') => unescaped.push('\n'),
+            Some('\\') => unescaped.push('\\'),
+            _ => {
+                unescaped.push('\\');
+                continue;
+            }
+        }
+
+        chars.next();
+    }
+
+    unescaped
+}
+
+/// Counts the line breaks a match of `hir` can cross at most, or `None` if
+/// there is no limit.
+fn line_breaks(hir: &Hir) -> Option<usize> {
+    match hir.kind() {
+        HirKind::Empty | HirKind::Look(_) => Some(0),
+        HirKind::Literal(literal) => Some(literal.0.iter().filter(|b| **b == b'\n').count()),
+        HirKind::Class(Class::Unicode(class)) => Some(usize::from(
+            class
+                .ranges()
+                .iter()
+                .any(|range| range.start() <= '\n' && '\n' <= range.end()),
+        )),
+        HirKind::Class(Class::Bytes(class)) => Some(usize::from(
+            class
+                .ranges()
+                .iter()
+                .any(|range| range.start() <= b'\n' && b'\n' <= range.end()),
+        )),
+        HirKind::Repetition(repetition) => match line_breaks(&repetition.sub)? {
+            0 => Some(0),
+            breaks => breaks.checked_mul(usize::try_from(repetition.max?).ok()?),
+        },
+        HirKind::Capture(capture) => line_breaks(&capture.sub),
+        HirKind::Concat(hirs) => hirs
+            .iter()
+            .try_fold(0usize, |sum, hir| sum.checked_add(line_breaks(hir)?)),
+        HirKind::Alternation(hirs) => hirs
+            .iter()
+            .try_fold(0, |most, hir| Some(most.max(line_breaks(hir)?))),
+    }
+}
+
+/// Keeps the last line of a regex error, which tells what is wrong without
+/// repeating the pattern.
+pub fn short_error(err: &RegexError) -> String {
+    let message = err.to_string();
+
+    message
+        .lines()
+        .last()
+        .unwrap_or_default()
+        .trim_start_matches("error: ")
+        .to_owned()
+}
+
+#[cfg(test)]
+mod query_tests {
+    use super::*;
+
+    fn query(text: &str) -> Query {
+        Query {
+            text: text.to_owned(),
+            ..Query::default()
+        }
+    }
+
+    #[test]
+    fn test_modes() {
+        let literal = query("a.b").compile().unwrap();
+
+        assert_eq!(literal.find_at("axb a.b", 0), Some((4, 7)));
+
+        let regex = Query {
+            regex: true,
+            ..query("a.b")
+        };
+
+        assert_eq!(regex.compile().unwrap().find_at("axb a.b", 0), Some((0, 3)));
+
+        let word = Query {
+            whole_word: true,
+            ignore_case: true,
+            ..query("id")
+        };
+        let pattern = word.compile().unwrap();
+
+        assert_eq!(pattern.find_at("idx ID", 0), Some((4, 6)));
+        assert_eq!(pattern.rfind_before("id id id", 6), Some((3, 5)));
+        assert_eq!(word.modes(), "[ignore case, word]");
+    }
+
+    #[test]
+    fn test_expand_captures() {
+        let pattern = Query {
+            regex: true,
+            ..query(r"(\w+)\.len\(\)")
+        }
+        .compile()
+        .unwrap();
+
+        assert_eq!(pattern.expand("n = v.len()", 4, "len(&$1)"), "len(&v)");
+        assert_eq!(query("v").compile().unwrap().expand("v", 0, "$1"), "$1");
+    }
+
+    #[test]
+    fn test_line_breaks() {
+        let spans = |text: &str, regex| {
+            Query {
+                regex,
+                ..query(text)
+            }
+            .compile()
+            .unwrap()
+            .spans()
+        };
+
+        assert_eq!(spans("a.b", true), 0);
+        assert_eq!(spans(r"a\nb\n", false), 2);
+        assert_eq!(spans(r"a\\nb", false), 0);
+        assert_eq!(spans(r"(a\n){3}|b", true), 3);
+        assert_eq!(spans(r"\s+", true), MAX_SPANS);
+        assert_eq!(spans("[^x]*", true), MAX_SPANS);
+        assert_eq!(spans(r"\S+", true), 0);
+    }
+
+    #[test]
+    fn test_literal_escapes() {
+        let pattern = query(r"a\nb").compile().unwrap();
+
+        assert_eq!(pattern.find_at("a\nb", 0), Some((0, 3)));
+
+        let pattern = query(r"a\\nb").compile().unwrap();
+
+        assert_eq!(pattern.find_at("a\nb a\\nb", 0), Some((4, 8)));
+        assert_eq!(
+            query(r"a\tb").compile().unwrap().find_at(r"a\tb", 0),
+            Some((0, 4))
+        );
+    }
+
+    #[test]
+    fn test_invalid_pattern() {
+        let err = Query {
+            regex: true,
+            ..query("(a")
+        }
+        .compile()
+        .unwrap_err();
+
+        assert_eq!(short_error(&err), "unclosed group");
+    }
+}
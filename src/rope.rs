@@ -0,0 +1,337 @@
+use crate::Row;
+
+use std::ops::Range;
+
+/// Sequence of rows backing a `Document`.
+///
+/// Rows are the leaves of an implicit treap ordered by line number, so
+/// looking up, inserting and removing a line are `O(log n)` wherever
+/// the edit happens in the file.
+pub struct Rope {
+    root: Option<Box<Node>>,
+    seed: u64,
+}
+
+struct Node {
+    row: Row,
+    priority: u64,
+    size: usize,
+    left: Option<Box<Node>>,
+    right: Option<Box<Node>>,
+}
+
+pub struct Iter<'a> {
+    stack: Vec<&'a Node>,
+}
+
+impl Node {
+    fn new(row: Row, priority: u64) -> Box<Self> {
+        Box::new(Self {
+            row,
+            priority,
+            size: 1,
+            left: None,
+            right: None,
+        })
+    }
+
+    fn update(&mut self) {
+        self.size = size(&self.left) + size(&self.right) + 1;
+    }
+}
+
+impl Default for Rope {
+    fn default() -> Self {
+        Self {
+            root: None,
+            seed: 0x2545_f491_4f6c_dd1d,
+        }
+    }
+}
+
+impl From<Vec<Row>> for Rope {
+    fn from(rows: Vec<Row>) -> Self {
+        let mut rope = Self::default();
+        let mut stack: Vec<Box<Node>> = Vec::new();
+
+        for row in rows {
+            let mut node = Node::new(row, rope.next_priority());
+            let mut last = None;
+
+            while let Some(mut top) = stack.pop() {
+                if top.priority >= node.priority {
+                    stack.push(top);
+                    break;
+                }
+
+                top.right = last;
+                top.update();
+                last = Some(top);
+            }
+
+            node.left = last;
+            node.update();
+            stack.push(node);
+        }
+
+        let mut last = None;
+
+        while let Some(mut top) = stack.pop() {
+            top.right = last;
+            top.update();
+            last = Some(top);
+        }
+
+        rope.root = last;
+
+        rope
+    }
+}
+
+impl Rope {
+    pub fn len(&self) -> usize {
+        size(&self.root)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.root.is_none()
+    }
+
+    pub fn get(&self, index: usize) -> Option<&Row> {
+        let mut index = index;
+        let mut node = self.root.as_deref()?;
+
+        loop {
+            let left = size(&node.left);
+
+            if index < left {
+                node = node.left.as_deref()?;
+            } else if index == left {
+                return Some(&node.row);
+            } else {
+                index -= left + 1;
+                node = node.right.as_deref()?;
+            }
+        }
+    }
+
+    pub fn get_mut(&mut self, index: usize) -> Option<&mut Row> {
+        let mut index = index;
+        let mut node = self.root.as_deref_mut()?;
+
+        loop {
+            let left = size(&node.left);
+
+            if index < left {
+                node = node.left.as_deref_mut()?;
+            } else if index == left {
+                return Some(&mut node.row);
+            } else {
+                index -= left + 1;
+                node = node.right.as_deref_mut()?;
+            }
+        }
+    }
+
+    pub fn insert(&mut self, index: usize, row: Row) {
+        let index = index.min(self.len());
+        let node = Node::new(row, self.next_priority());
+        let (left, right) = split(self.root.take(), index);
+
+        self.root = merge(merge(left, Some(node)), right);
+    }
+
+    pub fn push(&mut self, row: Row) {
+        self.insert(self.len(), row);
+    }
+
+    pub fn remove(&mut self, index: usize) -> Option<Row> {
+        if index >= self.len() {
+            return None;
+        }
+
+        let (left, right) = split(self.root.take(), index);
+        let (middle, right) = split(right, 1);
+
+        self.root = merge(left, right);
+
+        middle.map(|node| node.row)
+    }
+
+    pub fn iter(&self) -> Iter<'_> {
+        let mut iter = Iter { stack: Vec::new() };
+
+        iter.push_left(self.root.as_deref());
+
+        iter
+    }
+
+    /// Calls `callback` on every row in `range`, in order.
+    pub fn for_each_mut<F>(&mut self, range: Range<usize>, mut callback: F)
+    where
+        F: FnMut(usize, &mut Row),
+    {
+        visit_mut(self.root.as_deref_mut(), 0, &range, &mut callback);
+    }
+
+    fn next_priority(&mut self) -> u64 {
+        self.seed ^= self.seed << 13;
+        self.seed ^= self.seed >> 7;
+        self.seed ^= self.seed << 17;
+
+        self.seed
+    }
+}
+
+impl<'a> Iter<'a> {
+    fn push_left(&mut self, mut node: Option<&'a Node>) {
+        while let Some(n) = node {
+            self.stack.push(n);
+            node = n.left.as_deref();
+        }
+    }
+}
+
+impl<'a> Iterator for Iter<'a> {
+    type Item = &'a Row;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let node = self.stack.pop()?;
+
+        self.push_left(node.right.as_deref());
+
+        Some(&node.row)
+    }
+}
+
+fn size(node: &Option<Box<Node>>) -> usize {
+    node.as_ref().map_or(0, |n| n.size)
+}
+
+fn split(node: Option<Box<Node>>, index: usize) -> (Option<Box<Node>>, Option<Box<Node>>) {
+    match node {
+        None => (None, None),
+        Some(mut n) => {
+            if size(&n.left) >= index {
+                let (left, right) = split(n.left.take(), index);
+
+                n.left = right;
+                n.update();
+
+                (left, Some(n))
+            } else {
+                let index = index - size(&n.left) - 1;
+                let (left, right) = split(n.right.take(), index);
+
+                n.right = left;
+                n.update();
+
+                (Some(n), right)
+            }
+        }
+    }
+}
+
+fn merge(left: Option<Box<Node>>, right: Option<Box<Node>>) -> Option<Box<Node>> {
+    match (left, right) {
+        (None, node) | (node, None) => node,
+        (Some(mut l), Some(mut r)) => {
+            if l.priority > r.priority {
+                l.right = merge(l.right.take(), Some(r));
+                l.update();
+
+                Some(l)
+            } else {
+                r.left = merge(Some(l), r.left.take());
+                r.update();
+
+                Some(r)
+            }
+        }
+    }
+}
+
+fn visit_mut<F>(node: Option<&mut Node>, offset: usize, range: &Range<usize>, callback: &mut F)
+where
+    F: FnMut(usize, &mut Row),
+{
+    if let Some(node) = node {
+        if offset >= range.end || offset + node.size <= range.start {
+            return;
+        }
+
+        let index = offset + size(&node.left);
+
+        visit_mut(node.left.as_deref_mut(), offset, range, callback);
+
+        if range.contains(&index) {
+            callback(index, &mut node.row);
+        }
+
+        visit_mut(node.right.as_deref_mut(), index + 1, range, callback);
+    }
+}
+
+#[cfg(test)]
+mod rope_tests {
+    use super::*;
+
+    fn lines(rope: &Rope) -> Vec<String> {
+        rope.iter().map(|row| row.as_string().clone()).collect()
+    }
+
+    #[test]
+    fn test_from_and_get() {
+        let rope = Rope::from(
+            (0..100)
+                .map(|i| Row::from(&i.to_string()[..]))
+                .collect::<Vec<_>>(),
+        );
+
+        assert_eq!(rope.len(), 100);
+        assert_eq!(rope.get(42).map(Row::as_string), Some(&"42".to_owned()));
+        assert!(rope.get(100).is_none());
+        assert_eq!(lines(&rope).len(), 100);
+    }
+
+    #[test]
+    fn test_insert_and_remove() {
+        let mut rope = Rope::default();
+        let mut expected = Vec::new();
+
+        for i in 0..200 {
+            let index = (i * 7) % (expected.len() + 1);
+
+            rope.insert(index, Row::from(&i.to_string()[..]));
+            expected.insert(index, i.to_string());
+        }
+
+        assert_eq!(lines(&rope), expected);
+
+        for i in 0..100 {
+            let index = (i * 13) % expected.len();
+
+            assert_eq!(
+                rope.remove(index).map(|row| row.as_string().clone()),
+                Some(expected.remove(index))
+            );
+        }
+
+        assert_eq!(lines(&rope), expected);
+        assert!(rope.remove(expected.len()).is_none());
+    }
+
+    #[test]
+    fn test_for_each_mut() {
+        let mut rope = Rope::from(vec![Row::from("a"), Row::from("b"), Row::from("c")]);
+        let mut visited = Vec::new();
+
+        rope.for_each_mut(1..3, |index, row| {
+            row.insert(1, '!');
+            visited.push(index);
+        });
+
+        assert_eq!(visited, vec![1, 2]);
+        assert_eq!(lines(&rope), vec!["a", "b!", "c!"]);
+    }
+}
@@ -1,17 +1,25 @@
 use crate::highlight;
 use crate::HighlightOptions;
+use crate::Pattern;
 use crate::SearchDirection;
+use crate::Theme;
 
 use std::cmp;
+use std::ops::Range;
 use termion::color;
 use thiserror::Error;
 use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
 
+/// A line of text, indexed by grapheme everywhere outside this module.
+///
+/// `boundaries` caches the byte offset at which each grapheme starts, and
+/// `highlight` holds one entry per grapheme.
 #[derive(Default)]
 pub struct Row {
     pub is_highlighted: bool,
     string: String,
-    len: usize,
+    boundaries: Vec<usize>,
     highlight: Vec<highlight::Type>,
 }
 
@@ -25,45 +33,82 @@ pub enum RowError {
 
 impl From<&str> for Row {
     fn from(slice: &str) -> Self {
-        Self {
+        Self::from(String::from(slice))
+    }
+}
+
+impl From<String> for Row {
+    fn from(string: String) -> Self {
+        let mut row = Self {
             is_highlighted: false,
-            string: String::from(slice),
-            len: slice.graphemes(true).count(),
+            string,
+            boundaries: Vec::new(),
             highlight: Vec::new(),
-        }
+        };
+
+        row.update_boundaries();
+        row
     }
 }
 
 impl Row {
-    pub fn render(&self, start: usize, end: usize) -> String {
-        let end = cmp::min(end, self.string.len());
-        let start = cmp::min(start, end);
+    /// Renders the screen columns from `start` to `end` in the colors of
+    /// `theme`, painting the graphemes in `selection` with its background.
+    ///
+    /// Tabs are expanded to the next multiple of `tab_width`, and wide
+    /// graphemes cut by either edge are shown as spaces.
+    pub fn render(
+        &self,
+        start: usize,
+        end: usize,
+        selection: &Range<usize>,
+        tab_width: usize,
+        theme: &Theme,
+    ) -> String {
         let mut result = String::new();
         let mut current_highlight = &highlight::Type::None;
+        let mut selected = false;
 
-        for (index, grapheme) in self.string[..]
-            .graphemes(true)
-            .enumerate()
-            .skip(start)
-            .take(end - start)
-        {
-            if let Some(c) = grapheme.chars().next() {
-                let highlight_type = self.highlight.get(index).unwrap_or(current_highlight);
-
-                if highlight_type != current_highlight {
-                    current_highlight = highlight_type;
+        for (index, (grapheme, column, width)) in self.columns(tab_width).enumerate() {
+            if column >= end {
+                break;
+            }
 
-                    let start_highlight = format!("{}", color::Fg(highlight_type.to_color()),);
+            if column < start && column + width <= start {
+                continue;
+            }
 
-                    result.push_str(&start_highlight[..]);
-                }
+            if selection.contains(&index) != selected {
+                selected = !selected;
 
-                if c == '\t' {
-                    result.push(' ');
+                if selected {
+                    result.push_str(&format!("{}", color::Bg(theme.selection)));
                 } else {
-                    result.push(c);
+                    result.push_str(&format!("{}", color::Bg(color::Reset)));
                 }
             }
+
+            let highlight_type = self.highlight.get(index).unwrap_or(current_highlight);
+
+            if highlight_type != current_highlight {
+                current_highlight = highlight_type;
+
+                let start_highlight = format!("{}", color::Fg(highlight_type.to_color(theme)),);
+
+                result.push_str(&start_highlight[..]);
+            }
+
+            let visible = cmp::min(column + width, end) - cmp::max(column, start);
+
+            if grapheme == "\t" || visible < width {
+                result.push_str(&" ".repeat(visible));
+            } else {
+                result.push_str(grapheme);
+            }
+        }
+
+        if selected {
+            result.push_str(&format!("{}", color::Bg(color::Reset)));
         }
 
         let end_highlight = format!("{}", color::Fg(color::Reset),);
@@ -72,149 +117,193 @@ impl Row {
 
         result
     }
-    
+
+    /// Screen column at which grapheme `at` starts, or the width of the row
+    /// if `at` is past its end.
+    pub fn column(&self, at: usize, tab_width: usize) -> usize {
+        self.columns(tab_width)
+            .nth(at)
+            .map_or_else(|| self.width(tab_width), |(_, column, _)| column)
+    }
+
+    /// Index of the grapheme covering screen column `column`, or the length
+    /// of the row if `column` is past its end.
+    pub fn grapheme_at_column(&self, column: usize, tab_width: usize) -> usize {
+        self.columns(tab_width)
+            .position(|(_, start, width)| start + width > column)
+            .unwrap_or(self.len())
+    }
+
+    /// Number of screen columns the row takes.
+    pub fn width(&self, tab_width: usize) -> usize {
+        self.columns(tab_width)
+            .last()
+            .map_or(0, |(_, column, width)| column + width)
+    }
+
+    /// Yields each grapheme with the screen column it starts at and its width.
+    fn columns(&self, tab_width: usize) -> impl Iterator<Item = (&str, usize, usize)> {
+        let tab_width = tab_width.max(1);
+        let mut column = 0;
+
+        self.graphemes().map(move |grapheme| {
+            let start = column;
+            let width = if grapheme == "\t" {
+                tab_width - start % tab_width
+            } else {
+                grapheme.width()
+            };
+
+            column += width;
+            (grapheme, start, width)
+        })
+    }
+
     pub fn len(&self) -> usize {
-        self.len
+        self.boundaries.len()
     }
 
     pub fn is_empty(&self) -> bool {
-        self.len == 0
+        self.boundaries.is_empty()
     }
 
+    /// Inserts `c` before grapheme `at`, or at the end if `at` is past it.
+    ///
+    /// A combining character joins the grapheme before it, so the row does
+    /// not always grow by one grapheme.
     pub fn insert(&mut self, at: usize, c: char) {
-        if at >= self.len() {
-            self.string.push(c);
-            self.len += 1;
+        let index = self.byte_index(at);
+
+        self.string.insert(index, c);
+        self.update_boundaries();
+    }
 
+    pub fn delete(&mut self, at: usize) {
+        if at >= self.len() {
             return;
         }
 
-        let mut result: String = String::new();
-        let mut length = 0;
+        let start = self.byte_index(at);
+        let end = self.byte_index(at + 1);
 
-        for (index, grapheme) in self.string[..].graphemes(true).enumerate() {
-            length += 1;
-
-            if index == at {
-                length += 1;
-                result.push(c);
-            }
+        self.string.replace_range(start..end, "");
+        self.update_boundaries();
+    }
 
-            result.push_str(grapheme);
+    pub fn insert_str(&mut self, at: usize, string: &str) {
+        if string.is_empty() {
+            return;
         }
 
-        self.len = length;
-        self.string = result;
+        let tail = self.split(at);
+
+        self.append(&Row::from(string));
+        self.append(&tail);
     }
 
-    pub fn delete(&mut self, at: usize) {
-        if at >= self.len() {
+    pub fn delete_range(&mut self, start: usize, end: usize) {
+        if start >= end || start >= self.len() {
             return;
         }
 
-        let mut result: String = String::new();
-        let mut length = 0;
-
-        for (index, grapheme) in self.string[..].graphemes(true).enumerate() {
-            if index != at {
-                length += 1;
-                result.push_str(grapheme);
-            }
-        }
+        let tail = self.split(end);
 
-        self.len = length;
-        self.string = result;
+        self.split(start);
+        self.append(&tail);
     }
 
     pub fn append(&mut self, new: &Self) {
-        self.string = format!("{}{}", self.string, new.string);
-        self.len += new.len;
+        self.string.push_str(&new.string);
+        self.update_boundaries();
     }
 
     pub fn split(&mut self, at: usize) -> Self {
-        let mut row: String = String::new();
-        let mut length = 0;
-        let mut splitted_row: String = String::new();
-        let mut splitted_length = 0;
-
-        for (index, grapheme) in self.string[..].graphemes(true).enumerate() {
-            if index < at {
-                length += 1;
-                row.push_str(grapheme);
-            } else {
-                splitted_length += 1;
-                splitted_row.push_str(grapheme);
-            }
-        }
+        let index = self.byte_index(at);
+        let splitted_row = self.string.split_off(index);
 
-        self.string = row;
-        self.len = length;
+        self.update_boundaries();
+        self.highlight.truncate(self.len());
         self.is_highlighted = false;
 
-        Self {
-            is_highlighted: false,
-            string: splitted_row,
-            len: splitted_length,
-            highlight: Vec::new(),
+        Self::from(splitted_row)
+    }
+
+    /// Returns the grapheme index at which each visual line starts when the
+    /// row is wrapped to `width` columns, breaking after whitespace if `words`.
+    ///
+    /// A row that exactly fills its last line gets an empty line after it, so
+    /// the cursor always has somewhere to go at the end of the row.
+    pub fn wrap(&self, width: usize, words: bool, tab_width: usize) -> Vec<usize> {
+        let width = width.max(1);
+        let graphemes = self.columns(tab_width).collect::<Vec<_>>();
+        let mut starts = vec![0];
+        let mut start = 0;
+        let mut index = 0;
+
+        while let Some((_, column, grapheme_width)) = graphemes.get(index) {
+            if index > start && column + grapheme_width > graphemes[start].1 + width {
+                let mut end = index;
+
+                if words {
+                    if let Some(after_space) = (start + 1..=index)
+                        .rev()
+                        .find(|index| graphemes[index - 1].0.trim().is_empty())
+                    {
+                        end = after_space;
+                    }
+                }
+
+                starts.push(end);
+                start = end;
+                index = end;
+                continue;
+            }
+
+            index += 1;
         }
+
+        if start < self.len() && self.width(tab_width) >= graphemes[start].1 + width {
+            starts.push(self.len());
+        }
+
+        starts
     }
 
     pub fn as_bytes(&self) -> &[u8] {
         self.string.as_bytes()
     }
-    
-    pub fn find(&self, query: &str, at: usize, direction: SearchDirection) -> Option<usize> {
-        if at > self.len {
+
+    /// Returns the graphemes covered by the next match of `pattern` from
+    /// `at`, or by the last one starting before `at` when going backward.
+    pub fn find(
+        &self,
+        pattern: &Pattern,
+        at: usize,
+        direction: SearchDirection,
+    ) -> Option<Range<usize>> {
+        if at > self.len() {
             return None;
         }
 
-        let start = if direction == SearchDirection::Forward {
-            at
-        } else {
-            0
-        };
-        let end = if direction == SearchDirection::Forward {
-            self.len
-        } else {
-            at
-        };
-        let substring: String = self.string[..]
-            .graphemes(true)
-            .skip(start)
-            .take(end - start)
-            .collect();
-        let matching_byte_index = if direction == SearchDirection::Forward {
-            substring.find(query)
+        let index = self.byte_index(at);
+        let (start, end) = if direction == SearchDirection::Forward {
+            pattern.find_at(&self.string, index)?
         } else {
-            substring.rfind(query)
+            pattern.rfind_before(&self.string, index)?
         };
 
-        if let Some(matching_byte_index) = matching_byte_index {
-            for (grapheme_index, (byte_index, _)) in substring.grapheme_indices(true).enumerate() {
-                if matching_byte_index == byte_index {
-                    return Some(start + grapheme_index);
-                }
-            }
-        }
-
-        None
+        Some(self.grapheme_index(start)..self.grapheme_index(end))
     }
 
     pub fn highlight(
         &mut self,
         opts: &HighlightOptions,
-        word: &Option<String>,
+        word: Option<&Pattern>,
         start_with_comment: bool,
     ) -> bool {
-        let len = self.string.len();
-        let chars: Vec<char> = self.string.chars().collect();
-
         if self.is_highlighted && word.is_none() {
             if let Some(hl_type) = self.highlight.last() {
-                if *hl_type == highlight::Type::MultilineComment
-                    && len > 1
-                    && self.string[len - 2..] == *"*/"
-                {
+                if *hl_type == highlight::Type::MultilineComment && self.string.ends_with("*/") {
                     return true;
                 }
             }
@@ -222,13 +311,16 @@ impl Row {
             return false;
         }
 
+        // The first char of each grapheme, so indexes line up with graphemes.
+        let chars: Vec<char> = self.graphemes().filter_map(|g| g.chars().next()).collect();
+
         self.highlight = Vec::new();
         let mut index = 0;
         let mut in_ml_comment = start_with_comment;
 
         if in_ml_comment {
             let closing_index = if let Some(closing_index) = self.string.find("*/") {
-                closing_index + 2
+                self.grapheme_index(closing_index) + 2
             } else {
                 chars.len()
             };
@@ -263,9 +355,10 @@ impl Row {
             index += 1;
         }
 
+        self.highlight.truncate(chars.len());
         self.highlight_match(word);
 
-        if in_ml_comment && &self.string[self.string.len().saturating_sub(2)..] != "*/" {
+        if in_ml_comment && !self.string.ends_with("*/") {
             return true;
         }
 
@@ -278,25 +371,48 @@ impl Row {
         &self.string
     }
 
-    fn highlight_match(&mut self, word: &Option<String>) {
-        if let Some(word) = word {
-            if word.is_empty() {
-                return;
+    /// Byte offset of grapheme `at` in the row, or its length past the end.
+    pub fn byte_index(&self, at: usize) -> usize {
+        self.boundaries
+            .get(at)
+            .copied()
+            .unwrap_or(self.string.len())
+    }
+
+    /// Index of the grapheme starting at `byte_index`, or of the next one if
+    /// it falls inside a grapheme.
+    pub fn grapheme_index(&self, byte_index: usize) -> usize {
+        self.boundaries.partition_point(|start| *start < byte_index)
+    }
+
+    fn graphemes(&self) -> impl Iterator<Item = &str> {
+        (0..self.len()).map(|at| &self.string[self.byte_index(at)..self.byte_index(at + 1)])
+    }
+
+    fn update_boundaries(&mut self) {
+        self.boundaries = self
+            .string
+            .grapheme_indices(true)
+            .map(|(index, _)| index)
+            .collect();
+    }
+
+    /// Highlights the graphemes in `range` as part of a search match.
+    pub fn highlight_range(&mut self, range: Range<usize>) {
+        for i in range {
+            if let Some(hl_type) = self.highlight.get_mut(i) {
+                *hl_type = highlight::Type::Match;
             }
+        }
+    }
 
+    fn highlight_match(&mut self, word: Option<&Pattern>) {
+        if let Some(word) = word {
             let mut index = 0;
 
-            while let Some(search_match) = self.find(word, index, SearchDirection::Forward) {
-                if let Some(next_index) = search_match.checked_add(word[..].graphemes(true).count())
-                {
-                    for i in index.saturating_add(search_match)..next_index {
-                        self.highlight[i] = highlight::Type::Match;
-                    }
-
-                    index = next_index;
-                } else {
-                    break;
-                }
+            while let Some(found) = self.find(word, index, SearchDirection::Forward) {
+                index = found.end.max(index + 1);
+                self.highlight_range(found);
             }
         }
     }
@@ -322,7 +438,7 @@ impl Row {
             }
         }
 
-        for _ in 0..substring.len() {
+        for _ in substring.chars() {
             self.highlight.push(hl_type);
             *index += 1;
         }
@@ -528,9 +644,10 @@ impl Row {
         if opts.comments() && c == '/' && *index < chars.len() {
             if let Some(next_char) = chars.get(index.saturating_add(1)) {
                 if *next_char == '*' {
+                    let after_opening = self.byte_index(*index + 2);
                     let closing_index =
-                        if let Some(closing_index) = self.string[*index + 2..].find("*/") {
-                            *index + closing_index + 4
+                        if let Some(closing_index) = self.string[after_opening..].find("*/") {
+                            self.grapheme_index(after_opening + closing_index) + 2
                         } else {
                             chars.len()
                         };
@@ -556,14 +673,107 @@ fn is_separator(c: char) -> bool {
 #[cfg(test)]
 mod row_tests {
     use super::*;
+    use crate::FileType;
+    use crate::Query;
+    use proptest::prelude::*;
+
+    fn pattern(text: &str) -> Pattern {
+        Query {
+            text: text.to_owned(),
+            ..Query::default()
+        }
+        .compile()
+        .unwrap()
+    }
+
+    #[test]
+    fn test_wrap() {
+        let row = Row::from("the quick brown fox");
+
+        assert_eq!(row.wrap(8, false, 4), [0, 8, 16]);
+        assert_eq!(row.wrap(8, true, 4), [0, 4, 10, 16]);
+        assert_eq!(row.wrap(19, false, 4), [0, 19]);
+        assert_eq!(row.wrap(20, true, 4), [0]);
+        assert_eq!(Row::from("abcdefghij").wrap(4, true, 4), [0, 4, 8]);
+        assert_eq!(Row::from("漢字漢字").wrap(3, false, 4), [0, 1, 2, 3]);
+        assert_eq!(Row::default().wrap(4, true, 4), [0]);
+    }
+
+    #[test]
+    fn test_columns() {
+        let row = Row::from("a\t漢👨\u{200d}👩\u{200d}👧e\u{301}");
+
+        assert_eq!(row.len(), 5);
+        assert_eq!(row.width(4), 9);
+        assert_eq!(row.width(8), 13);
+        assert_eq!(
+            (0..=5).map(|at| row.column(at, 4)).collect::<Vec<_>>(),
+            [0, 1, 4, 6, 8, 9]
+        );
+        assert_eq!(row.grapheme_at_column(2, 4), 1);
+        assert_eq!(row.grapheme_at_column(5, 4), 2);
+        assert_eq!(row.grapheme_at_column(9, 4), 5);
+    }
+
+    #[test]
+    fn test_render_columns() {
+        let row = Row::from("\t漢x");
+        let plain = |start, end| {
+            row.render(start, end, &(0..0), 4, &Theme::default())
+                .replace(&format!("{}", color::Fg(color::Reset)), "")
+        };
+
+        assert_eq!(plain(0, 7), "    漢x");
+        assert_eq!(plain(2, 7), "  漢x");
+        assert_eq!(plain(5, 7), " x");
+        assert_eq!(plain(0, 5), "     ");
+    }
 
     #[test]
     fn test_find() {
         let row = Row::from("test123");
 
-        assert_eq!(row.find("t", 0, SearchDirection::Forward), Some(0));
-        assert_eq!(row.find("t", 2, SearchDirection::Forward), Some(3));
-        assert_eq!(row.find("t", 5, SearchDirection::Forward), None);
+        assert_eq!(
+            row.find(&pattern("t"), 0, SearchDirection::Forward),
+            Some(0..1)
+        );
+        assert_eq!(
+            row.find(&pattern("t"), 2, SearchDirection::Forward),
+            Some(3..4)
+        );
+        assert_eq!(row.find(&pattern("t"), 5, SearchDirection::Forward), None);
+    }
+
+    #[test]
+    fn test_find_regex_graphemes() {
+        let row = Row::from("é = 42; x = 7");
+        let number = Query {
+            text: r"\d+".to_owned(),
+            regex: true,
+            ..Query::default()
+        }
+        .compile()
+        .unwrap();
+
+        assert_eq!(row.find(&number, 0, SearchDirection::Forward), Some(4..6));
+        assert_eq!(
+            row.find(&number, 13, SearchDirection::Backward),
+            Some(12..13)
+        );
+        assert_eq!(row.find(&number, 4, SearchDirection::Backward), None);
+    }
+
+    #[test]
+    fn test_render_selection() {
+        let row = Row::from("abcd");
+        let theme = Theme::default();
+        let selected = format!("{}", color::Bg(theme.selection));
+        let reset = format!("{}", color::Bg(color::Reset));
+        let rendered = row.render(0, 4, &(1..3), 4, &theme);
+
+        assert_eq!(rendered.matches(&selected).count(), 1);
+        assert!(rendered.contains(&format!("{selected}bc{reset}d")));
+        assert!(!row.render(0, 4, &(0..0), 4, &theme).contains(&selected));
     }
 
     #[test]
@@ -579,13 +789,13 @@ mod row_tests {
             highlight::Type::Number,
             highlight::Type::Number,
         ];
-        row.highlight_match(&Some("t".to_string()));
+        row.highlight_match(Some(&pattern("t")));
         assert_eq!(
             vec![
                 highlight::Type::Match,
                 highlight::Type::Char,
                 highlight::Type::Char,
-                highlight::Type::Char,
+                highlight::Type::Match,
                 highlight::Type::Number,
                 highlight::Type::Number,
                 highlight::Type::Number,
@@ -593,4 +803,150 @@ mod row_tests {
             row.highlight
         )
     }
+
+    /// Text mixing ASCII, code that highlights, combining marks, wide
+    /// characters, emoji sequences, tabs and arbitrary printable chars.
+    fn text() -> impl Strategy<Value = String> {
+        let piece = prop_oneof![
+            Just("a".to_owned()),
+            Just(" ".to_owned()),
+            Just("\t".to_owned()),
+            Just("é".to_owned()),
+            Just("e\u{301}".to_owned()),
+            Just("\u{301}".to_owned()),
+            Just("漢字".to_owned()),
+            Just("👨\u{200d}👩\u{200d}👧".to_owned()),
+            Just("🇫🇷".to_owned()),
+            Just("fn".to_owned()),
+            Just("42".to_owned()),
+            Just("/*".to_owned()),
+            Just("*/".to_owned()),
+            Just("//".to_owned()),
+            Just("\"".to_owned()),
+            Just("'".to_owned()),
+            "\\PC",
+        ];
+
+        prop::collection::vec(piece, 0..16).prop_map(|pieces| pieces.concat())
+    }
+
+    fn assert_consistent(row: &Row) {
+        let boundaries = row
+            .string
+            .grapheme_indices(true)
+            .map(|(index, _)| index)
+            .collect::<Vec<_>>();
+
+        assert_eq!(row.boundaries, boundaries);
+        assert_eq!(row.len(), boundaries.len());
+        assert_eq!(row.byte_index(row.len()), row.string.len());
+
+        for (at, byte) in boundaries.iter().enumerate() {
+            assert_eq!(row.byte_index(at), *byte);
+            assert_eq!(row.grapheme_index(*byte), at);
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn test_insert_delete_keep_boundaries(text in text(), c in "\\PC", at in 0..20usize) {
+            let c = c.chars().next().unwrap();
+            let mut row = Row::from(&text[..]);
+            let mut expected = text.clone();
+
+            expected.insert(row.byte_index(at), c);
+            row.insert(at, c);
+            assert_consistent(&row);
+            prop_assert_eq!(row.as_string(), &expected);
+
+            let len = row.len();
+            let start = row.byte_index(at);
+            let end = row.byte_index(at + 1);
+
+            expected.replace_range(start..end, "");
+            row.delete(at);
+            assert_consistent(&row);
+            prop_assert_eq!(row.as_string(), &expected);
+            prop_assert!(row.len() < len || at >= len);
+        }
+
+        #[test]
+        fn test_split_append_round_trip(text in text(), tail in text(), at in 0..20usize) {
+            let mut row = Row::from(&text[..]);
+            let split = row.split(at);
+
+            assert_consistent(&row);
+            assert_consistent(&split);
+            prop_assert_eq!(format!("{}{}", row.as_string(), split.as_string()), text.clone());
+
+            row.append(&split);
+            row.append(&Row::from(&tail[..]));
+            assert_consistent(&row);
+            prop_assert_eq!(row.as_string(), &format!("{text}{tail}"));
+        }
+
+        #[test]
+        fn test_insert_str_delete_range(text in text(), inserted in text(), at in 0..20usize) {
+            let mut row = Row::from(&text[..]);
+            let len = row.len();
+
+            row.insert_str(at, &inserted);
+            assert_consistent(&row);
+
+            let start = at.min(len);
+
+            row.delete_range(start, start + Row::from(&inserted[..]).len());
+            assert_consistent(&row);
+        }
+
+        #[test]
+        fn test_highlight_covers_each_grapheme(text in text(), comment: bool, word in text()) {
+            let opts = FileType::new().from("main.rs");
+            let word = Query { text: word, ..Query::default() }.compile().unwrap();
+            let mut row = Row::from(&text[..]);
+
+            row.highlight(opts.highlight_options(), None, comment);
+            prop_assert_eq!(row.highlight.len(), row.len());
+
+            row.highlight(opts.highlight_options(), Some(&word), comment);
+            prop_assert_eq!(row.highlight.len(), row.len());
+        }
+
+        #[test]
+        fn test_render_expands_tabs(
+            text in text(),
+            start in 0..40usize,
+            width in 0..40usize,
+            tab_width in 1..9usize,
+        ) {
+            let opts = FileType::new().from("main.rs");
+            let escapes = regex::Regex::new("\x1B\\[[0-9;]*m").unwrap();
+            let mut row = Row::from(&text[..]);
+            let mut expanded = String::new();
+
+            row.highlight(opts.highlight_options(), None, false);
+
+            for (grapheme, _, width) in row.columns(tab_width) {
+                if grapheme == "\t" {
+                    expanded.push_str(&" ".repeat(width));
+                } else {
+                    expanded.push_str(grapheme);
+                }
+            }
+
+            let rendered = row.render(0, usize::MAX, &(1..3), tab_width, &Theme::default());
+
+            prop_assert_eq!(escapes.replace_all(&rendered, ""), expanded);
+
+            let rendered = row.render(start, start + width, &(0..0), tab_width, &Theme::default());
+            let visible = row.width(tab_width).min(start + width).saturating_sub(start);
+            let columns = escapes
+                .replace_all(&rendered, "")
+                .graphemes(true)
+                .map(|grapheme| grapheme.width())
+                .sum::<usize>();
+
+            prop_assert!(columns <= visible);
+        }
+    }
 }
@@ -0,0 +1,368 @@
+//! Crash recovery journal for unsaved edits.
+//!
+//! While a document has unsaved changes, revise periodically writes a swap
+//! file named `.<filename>.revise.swp` next to it, or under
+//! `$XDG_STATE_HOME/revise/swap/` (default `~/.local/state/revise/swap/`)
+//! when the directory of the file is not writable. In the state directory
+//! the swap file is named after the absolute path of the edited file, with
+//! every `%` replaced by `%25` and every `/` by `%2F`. The swap file is
+//! removed on save and on quit,
+//! so one that is still around when the file is reopened means the editor
+//! did not exit cleanly.
+//!
+//! A swap file is UTF-8 text made of a header and a body separated by a
+//! `---` line:
+//!
+//! ```text
+//! revise swap 1
+//! pid: 4242
+//! file: /home/user/notes.md
+//! line-ending: LF
+//! final-newline: yes
+//! ---
+//! first row
+//! second row
+//! ```
+//!
+//! The first line identifies the format and its version. `pid` is the
+//! process that wrote the file and is used to tell a stale swap file from
+//! one owned by a running editor, which is never written over. `line-ending` is `LF`, `CRLF` or `CR` and
+//! `final-newline` is `yes` or `no`; both describe how the document will be
+//! written on save. Every row of the document follows the separator,
+//! terminated by `\n` regardless of the line ending of the file.
+
+use crate::config;
+use crate::diff;
+use crate::Document;
+use crate::LineEnding;
+
+use std::fs::{self, File};
+use std::io::{BufWriter, Error as IOError, ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+use thiserror::Error;
+
+const MAGIC: &str = "revise swap 1";
+const SEPARATOR: &str = "---";
+
+pub struct Swap {
+    pub path: PathBuf,
+    pub pid: u32,
+    pub filename: String,
+    pub line_ending: LineEnding,
+    pub final_newline: bool,
+    pub lines: Vec<String>,
+}
+
+#[derive(Debug, Error)]
+pub enum SwapError {
+    #[error("cannot read swap file: {0}")]
+    Io(#[from] IOError),
+    #[error("not a revise swap file")]
+    Format,
+    #[error("invalid swap header: {0}")]
+    Header(String),
+}
+
+impl Swap {
+    /// Returns the swap file left behind for `filename`, if any.
+    pub fn find(filename: &str) -> Option<PathBuf> {
+        candidates(filename).into_iter().find(|path| path.is_file())
+    }
+
+    /// # Errors
+    ///
+    /// Will return `SwapError` if the file cannot be read or is malformed
+    pub fn read(path: &Path) -> Result<Self, SwapError> {
+        let contents = fs::read_to_string(path)?;
+        let (header, body) = contents
+            .split_once(&format!("\n{SEPARATOR}\n"))
+            .ok_or(SwapError::Format)?;
+        let mut header_lines = header.lines();
+
+        if header_lines.next() != Some(MAGIC) {
+            return Err(SwapError::Format);
+        }
+
+        let mut swap = Self {
+            path: path.to_path_buf(),
+            pid: 0,
+            filename: String::new(),
+            line_ending: LineEnding::default(),
+            final_newline: true,
+            lines: Vec::new(),
+        };
+
+        for line in header_lines {
+            let (key, value) = line
+                .split_once(": ")
+                .ok_or_else(|| SwapError::Header(line.to_owned()))?;
+
+            match key {
+                "pid" => {
+                    swap.pid = value
+                        .parse()
+                        .map_err(|_| SwapError::Header(line.to_owned()))?
+                }
+                "file" => swap.filename = value.to_owned(),
+                "line-ending" => {
+                    swap.line_ending = value
+                        .parse()
+                        .map_err(|_| SwapError::Header(line.to_owned()))?
+                }
+                "final-newline" => swap.final_newline = value == "yes",
+                _ => return Err(SwapError::Header(line.to_owned())),
+            }
+        }
+
+        swap.lines = body.split_terminator('\n').map(str::to_owned).collect();
+
+        Ok(swap)
+    }
+
+    /// Writes the contents of `document` to its swap file and returns where
+    /// it was written. The swap file at `kept`, which the user chose to keep,
+    /// is left alone.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Error` if no candidate location is writable, or if
+    /// another running editor owns the swap file
+    pub fn write(document: &Document, kept: Option<&Path>) -> Result<Option<PathBuf>, IOError> {
+        let Some(filename) = &document.filename else {
+            return Ok(None);
+        };
+        let mut last_error = None;
+
+        for path in candidates(filename)
+            .into_iter()
+            .filter(|path| Some(path.as_path()) != kept)
+        {
+            if let Some(swap) = Swap::read(&path).ok().filter(|swap| !swap.is_stale()) {
+                return Err(IOError::new(
+                    ErrorKind::AlreadyExists,
+                    format!("{} is being edited by process {}", swap.filename, swap.pid),
+                ));
+            }
+
+            match write_to(&path, filename, document) {
+                Ok(_) => return Ok(Some(path)),
+                Err(err) => last_error = Some(err),
+            }
+        }
+
+        match last_error {
+            Some(err) => Err(err),
+            None => Ok(None),
+        }
+    }
+
+    /// A swap file is stale when the editor that wrote it is no longer running.
+    pub fn is_stale(&self) -> bool {
+        self.pid == process::id() || !is_running(self.pid)
+    }
+
+    /// Describes the hunks turning what `document` holds into the swap file.
+    pub fn diff(&self, document: &Document) -> String {
+        let lines: Vec<&str> = self.lines.iter().map(String::as_str).collect();
+        let hunks = document.diff(&lines);
+
+        if hunks.is_empty() {
+            return String::from("Swap file is identical to the file.");
+        }
+
+        format!(
+            "Swap file differs from the file at {}.",
+            diff::describe(&hunks)
+        )
+    }
+
+    pub fn remove(path: &Path) {
+        let _ = fs::remove_file(path);
+    }
+}
+
+fn candidates(filename: &str) -> Vec<PathBuf> {
+    let path = fs::canonicalize(filename).unwrap_or_else(|_| PathBuf::from(filename));
+    let mut candidates = Vec::new();
+
+    if let Some(name) = path.file_name() {
+        candidates.push(path.with_file_name(format!(".{}.revise.swp", name.to_string_lossy())));
+    }
+
+    if let Some(dir) = config::state_dir().map(|dir| dir.join("swap")) {
+        let encoded = path
+            .to_string_lossy()
+            .replace('%', "%25")
+            .replace('/', "%2F");
+
+        candidates.push(dir.join(format!("{encoded}.swp")));
+    }
+
+    candidates
+}
+
+/// Tells whether process `pid` exists, even if owned by another user.
+fn is_running(pid: u32) -> bool {
+    let Ok(pid) = libc::pid_t::try_from(pid) else {
+        return false;
+    };
+
+    // Signal 0 only checks that the process exists and may be signalled.
+    pid > 0
+        && (unsafe { libc::kill(pid, 0) } == 0
+            || IOError::last_os_error().raw_os_error() == Some(libc::EPERM))
+}
+
+fn write_to(path: &Path, filename: &str, document: &Document) -> Result<(), IOError> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    let temp_path = path.with_extension("swp.tmp");
+    let mut writer = BufWriter::new(File::create(&temp_path)?);
+    let final_newline = if document.has_final_newline() {
+        "yes"
+    } else {
+        "no"
+    };
+
+    writeln!(writer, "{MAGIC}")?;
+    writeln!(writer, "pid: {}", process::id())?;
+    writeln!(writer, "file: {filename}")?;
+    writeln!(writer, "line-ending: {}", document.line_ending())?;
+    writeln!(writer, "final-newline: {final_newline}")?;
+    writeln!(writer, "{SEPARATOR}")?;
+
+    for row in document.rows() {
+        writer.write_all(row.as_bytes())?;
+        writer.write_all(b"\n")?;
+    }
+
+    writer
+        .into_inner()
+        .map_err(|err| err.into_error())?
+        .sync_all()?;
+    fs::rename(&temp_path, path)
+}
+
+#[cfg(test)]
+mod swap_tests {
+    use super::*;
+    use crate::Position;
+    use std::env;
+
+    fn edited_document(name: &str) -> (Document, PathBuf) {
+        let path = env::temp_dir().join(format!("{}-{name}", process::id()));
+
+        fs::write(&path, "fn main() {\r\n}\r\n").unwrap();
+
+        let mut document = Document::open(path.to_str().unwrap()).unwrap();
+
+        for (x, c) in "let a = 1;".chars().enumerate() {
+            document.insert(&Position { x, y: 1 }, c).unwrap();
+        }
+        document.insert(&Position { x: 10, y: 1 }, '\n').unwrap();
+
+        (document, path)
+    }
+
+    #[test]
+    fn test_recover_after_crash() {
+        let (document, path) = edited_document("revise-swap-crash.rs");
+        let swap_path = Swap::write(&document, None).unwrap().unwrap();
+
+        // Simulate the editor being killed: the document is dropped without
+        // saving and the swap file is left behind.
+        drop(document);
+
+        let filename = path.to_str().unwrap();
+
+        assert_eq!(Swap::find(filename), Some(swap_path.clone()));
+
+        let swap = Swap::read(&swap_path).unwrap();
+
+        assert!(swap.is_stale());
+        assert_eq!(swap.line_ending, LineEnding::CrLf);
+        assert_eq!(swap.lines, vec!["fn main() {", "let a = 1;", "}"]);
+
+        let mut document = Document::open(filename).unwrap();
+
+        assert_eq!(
+            swap.diff(&document),
+            "Swap file differs from the file at line 2: 1 added."
+        );
+
+        document.recover(swap);
+        assert!(document.is_changed());
+        document.save().unwrap();
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            "fn main() {\r\nlet a = 1;\r\n}\r\n"
+        );
+
+        Swap::remove(&swap_path);
+        fs::remove_file(&path).unwrap();
+        assert_eq!(Swap::find(filename), None);
+    }
+
+    #[test]
+    fn test_live_swap_is_not_written_over() {
+        let (document, path) = edited_document("revise-swap-live.rs");
+        let swap_path = candidates(path.to_str().unwrap()).remove(0);
+
+        // Process 1 is always running.
+        fs::write(
+            &swap_path,
+            format!("{MAGIC}\npid: 1\nfile: x\n{SEPARATOR}\nmine\n"),
+        )
+        .unwrap();
+
+        assert!(!Swap::read(&swap_path).unwrap().is_stale());
+        assert!(Swap::write(&document, None).is_err());
+        assert_eq!(Swap::read(&swap_path).unwrap().lines, vec!["mine"]);
+
+        Swap::remove(&swap_path);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_kept_swap_is_left_alone() {
+        let (document, path) = edited_document("revise-swap-kept.rs");
+        let swap_path = candidates(path.to_str().unwrap()).remove(0);
+        let stale = format!("{MAGIC}\npid: {}\nfile: x\n{SEPARATOR}\nold\n", u32::MAX);
+
+        fs::write(&swap_path, &stale).unwrap();
+
+        let written = Swap::write(&document, Some(&swap_path)).unwrap();
+
+        assert_ne!(written.as_ref(), Some(&swap_path));
+        assert_eq!(fs::read_to_string(&swap_path).unwrap(), stale);
+
+        if let Some(written) = written {
+            Swap::remove(&written);
+        }
+        Swap::remove(&swap_path);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_state_dir_names_are_distinct() {
+        let encoded = |filename| candidates(filename).pop().unwrap();
+
+        assert_ne!(encoded("/tmp/a%2Fb"), encoded("/tmp/a/b"));
+        assert_ne!(encoded("/tmp/a%b"), encoded("/tmp/a/b"));
+    }
+
+    #[test]
+    fn test_read_rejects_truncated_swap() {
+        let path = env::temp_dir().join(format!("revise-swap-truncated-{}.swp", process::id()));
+
+        fs::write(&path, "revise swap 1\npid: 1\nfile: /tmp/x").unwrap();
+        assert!(matches!(Swap::read(&path), Err(SwapError::Format)));
+
+        fs::write(&path, "revise swap 1\npid: abc\n---\n").unwrap();
+        assert!(matches!(Swap::read(&path), Err(SwapError::Header(_))));
+        fs::remove_file(&path).unwrap();
+    }
+}
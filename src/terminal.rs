@@ -1,12 +1,33 @@
 use crate::Position;
 
-use std::io::{stdin, stdout, Error, Stdout, Write};
-use termion::event::Key;
+use signal_hook::consts::SIGWINCH;
+use signal_hook::iterator::Signals;
+use std::io::{stdin, stdout, Error, ErrorKind, Stdout, Write};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::thread;
+use std::time::Duration;
+use termion::event::{Event, Key};
 use termion::input::TermRead;
 use termion::raw::{IntoRawMode, RawTerminal};
 use termion::terminal_size;
 use termion::{clear, color, cursor};
 
+const PASTE_START: &[u8] = b"\x1B[200~";
+const PASTE_END: &[u8] = b"\x1B[201~";
+
+/// A key read from the terminal, including the modified keys termion
+/// reports as unsupported sequences.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Input {
+    Key(Key),
+    /// A movement key pressed with Shift held down.
+    Shift(Key),
+    /// Text pasted through the terminal with bracketed paste.
+    Paste(String),
+    /// The terminal window changed size.
+    Resize,
+}
+
 pub struct Size {
     pub width: u16,
     pub height: u16,
@@ -14,23 +35,74 @@ pub struct Size {
 
 pub struct Terminal {
     size: Size,
+    keys: Receiver<Result<Input, Error>>,
     _stdout: RawTerminal<Stdout>,
 }
 
 impl Terminal {
     /// # Errors
     ///
-    /// Will return `Error` if it fails to get terminal size  
-    /// or if it fails to switch to raw mode
+    /// Will return `Error` if it fails to get terminal size,
+    /// to listen for resizes or to switch to raw mode
     pub fn new() -> Result<Self, Error> {
         let size = terminal_size()?;
+        let (sender, keys) = mpsc::channel();
+        let mut signals = Signals::new([SIGWINCH])?;
+        let resizes = sender.clone();
+
+        // Resizes are queued with the keys so a blocked read wakes up to redraw.
+        thread::spawn(move || {
+            for _ in signals.forever() {
+                if resizes.send(Ok(Input::Resize)).is_err() {
+                    break;
+                }
+            }
+        });
+
+        // Keys are read on their own thread so the editor can wake up on a
+        // timeout and do background work while the user is idle.
+        thread::spawn(move || {
+            let mut pasted: Option<String> = None;
+
+            for event in stdin().lock().events() {
+                let input = match event {
+                    Ok(Event::Unsupported(bytes)) if bytes == PASTE_START => {
+                        pasted = Some(String::new());
+                        continue;
+                    }
+                    Ok(Event::Unsupported(bytes)) if bytes == PASTE_END => match pasted.take() {
+                        Some(text) => Ok(Input::Paste(text)),
+                        None => continue,
+                    },
+                    Ok(Event::Key(key)) if pasted.is_some() => {
+                        if let (Some(text), Key::Char(c)) = (&mut pasted, key) {
+                            text.push(c);
+                        }
+                        continue;
+                    }
+                    Ok(event) => match decode(event) {
+                        Some(input) => Ok(input),
+                        None => continue,
+                    },
+                    Err(err) => Err(err),
+                };
+
+                if sender.send(input).is_err() {
+                    break;
+                }
+            }
+        });
+
+        let raw_stdout = stdout().into_raw_mode()?;
+
+        // Ask the terminal to wrap pasted text in markers, so it is inserted
+        // as is instead of being replayed as key presses.
+        print!("\x1B[?2004h");
 
         Ok(Self {
-            size: Size {
-                width: size.0,
-                height: size.1.saturating_sub(2),
-            },
-            _stdout: stdout().into_raw_mode()?,
+            size: Self::text_area(size),
+            keys,
+            _stdout: raw_stdout,
         })
     }
 
@@ -38,6 +110,24 @@ impl Terminal {
         &self.size
     }
 
+    /// Reads the terminal size again after a resize.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Error` if it fails to get terminal size
+    pub fn update_size(&mut self) -> Result<(), Error> {
+        self.size = Self::text_area(terminal_size()?);
+
+        Ok(())
+    }
+
+    fn text_area((width, height): (u16, u16)) -> Size {
+        Size {
+            width,
+            height: height.saturating_sub(2),
+        }
+    }
+
     pub fn clear_screen() {
         print!("{}", clear::All);
     }
@@ -64,13 +154,41 @@ impl Terminal {
         stdout().flush()
     }
 
+    /// Waits for a key, skipping pastes and resizes.
+    ///
     /// # Errors
     ///
     /// Will return an error if it fails to read key
-    pub fn read_key() -> Result<Key, Error> {
+    pub fn read_key(&self) -> Result<Key, Error> {
         loop {
-            if let Some(key) = stdin().lock().keys().next() {
-                return key;
+            match self.read_input()? {
+                Input::Key(key) | Input::Shift(key) => return Ok(key),
+                Input::Paste(_) | Input::Resize => (),
+            }
+        }
+    }
+
+    /// # Errors
+    ///
+    /// Will return an error if it fails to read key
+    pub fn read_input(&self) -> Result<Input, Error> {
+        match self.keys.recv() {
+            Ok(input) => input,
+            Err(_) => Err(Error::new(ErrorKind::UnexpectedEof, "input closed")),
+        }
+    }
+
+    /// Waits at most `timeout` for input, returning `None` if none came.
+    ///
+    /// # Errors
+    ///
+    /// Will return an error if it fails to read key
+    pub fn read_input_timeout(&self, timeout: Duration) -> Result<Option<Input>, Error> {
+        match self.keys.recv_timeout(timeout) {
+            Ok(input) => input.map(Some),
+            Err(RecvTimeoutError::Timeout) => Ok(None),
+            Err(RecvTimeoutError::Disconnected) => {
+                Err(Error::new(ErrorKind::UnexpectedEof, "input closed"))
             }
         }
     }
@@ -103,3 +221,30 @@ impl Terminal {
         print!("{}", color::Fg(color::Reset));
     }
 }
+
+impl Drop for Terminal {
+    fn drop(&mut self) {
+        print!("\x1B[?2004l");
+        let _ = Terminal::flush();
+    }
+}
+
+fn decode(event: Event) -> Option<Input> {
+    match event {
+        Event::Key(key) => Some(Input::Key(key)),
+        // xterm reports Shift with a `1;2` parameter, e.g. `ESC [ 1 ; 2 D`.
+        Event::Unsupported(bytes) => match bytes[..] {
+            [0x1B, b'[', b'1', b';', b'2', c] => match c {
+                b'A' => Some(Input::Shift(Key::Up)),
+                b'B' => Some(Input::Shift(Key::Down)),
+                b'C' => Some(Input::Shift(Key::Right)),
+                b'D' => Some(Input::Shift(Key::Left)),
+                b'H' => Some(Input::Shift(Key::Home)),
+                b'F' => Some(Input::Shift(Key::End)),
+                _ => None,
+            },
+            _ => None,
+        },
+        Event::Mouse(_) => None,
+    }
+}
@@ -0,0 +1,1136 @@
+use crate::Document;
+use crate::Position;
+use crate::Row;
+
+use std::mem;
+use termion::event::Key;
+use unicode_segmentation::UnicodeSegmentation;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Mode {
+    #[default]
+    Normal,
+    Insert,
+    Visual,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Motion {
+    Left,
+    Right,
+    Up,
+    Down,
+    WordForward,
+    WordBackward,
+    WordEnd,
+    LineStart,
+    LineEnd,
+    /// `gg`: the line given by the count, or the first one.
+    FirstLine,
+    /// `G`: the line given by the count, or the last one.
+    LastLine,
+    Find(char),
+    Till(char),
+    FindBackward(char),
+    TillBackward(char),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Operator {
+    Delete,
+    Change,
+    Yank,
+}
+
+/// Where `i`, `a`, `I`, `A`, `o` and `O` start inserting.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Insert {
+    Before,
+    After,
+    LineStart,
+    LineEnd,
+    LineBelow,
+    LineAbove,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Action {
+    Move(Motion),
+    Operate(Operator, Motion),
+    /// `dd`, `cc` and `yy`.
+    OperateLines(Operator),
+    /// An operator typed in visual mode.
+    OperateSelection(Operator),
+    Insert(Insert),
+    Visual,
+    Paste {
+        before: bool,
+    },
+    Undo,
+    Redo,
+    Repeat,
+    CommandLine,
+}
+
+/// The keys typed so far, read as a command.
+#[derive(Debug, PartialEq)]
+pub enum Parse<T> {
+    Done(T),
+    /// The keys start a command and more are needed.
+    Pending,
+    Invalid,
+}
+
+/// Text yanked or deleted by the last operator.
+#[derive(Default)]
+pub struct Register {
+    pub text: String,
+    /// Whether the text is whole lines, each ending with `\n`.
+    pub linewise: bool,
+}
+
+/// State of the vi layer between key presses.
+#[derive(Default)]
+pub struct Vi {
+    pub mode: Mode,
+    /// Keys of the command being typed in normal or visual mode.
+    pub pending: Vec<Key>,
+    pub register: Register,
+    /// Keys of the last change, replayed by `.`.
+    pub last_change: Vec<Key>,
+    /// Keys of a change still being typed in insert mode.
+    pub recording: Option<Vec<Key>>,
+    pub replaying: bool,
+}
+
+/// What the editor still has to do for a key the vi layer has seen.
+#[derive(Debug, PartialEq)]
+pub enum Outcome {
+    /// The key is left to the keymap, or typed as text in insert mode.
+    Unhandled,
+    Handled,
+    Undo(usize),
+    Redo(usize),
+    CommandLine,
+    /// `.`: keys to handle one at a time as if typed, after which
+    /// `replaying` is to be cleared.
+    Replay(Vec<Key>),
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Class {
+    Blank,
+    Word,
+    Punctuation,
+    EmptyLine,
+}
+
+impl Mode {
+    pub fn name(self) -> &'static str {
+        match self {
+            Mode::Normal => "NORMAL",
+            Mode::Insert => "INSERT",
+            Mode::Visual => "VISUAL",
+        }
+    }
+}
+
+impl Motion {
+    /// Whether an operator with this motion works on whole lines.
+    pub fn linewise(self) -> bool {
+        matches!(
+            self,
+            Motion::Up | Motion::Down | Motion::FirstLine | Motion::LastLine
+        )
+    }
+
+    /// Whether an operator with this motion includes the grapheme it stops on.
+    pub fn inclusive(self) -> bool {
+        matches!(self, Motion::WordEnd | Motion::Find(_) | Motion::Till(_))
+    }
+}
+
+impl Vi {
+    /// Handles `key` typed in `document` with the cursor at `cursor`.
+    /// `selection` is the visual selection, for operators typed in visual
+    /// mode.
+    pub fn key(
+        &mut self,
+        document: &mut Document,
+        cursor: &mut Position,
+        selection: Option<(Position, Position)>,
+        key: Key,
+    ) -> Outcome {
+        if self.mode == Mode::Insert {
+            // Only keys editing the text are replayed by `.`, not commands
+            // such as saving or searching.
+            let edit = matches!(key, Key::Char(_) | Key::Backspace | Key::Delete | Key::Esc);
+
+            if let (Some(keys), true) = (&mut self.recording, edit) {
+                keys.push(key);
+            }
+
+            if key != Key::Esc {
+                return Outcome::Unhandled;
+            }
+
+            if let Some(keys) = self.recording.take() {
+                self.last_change = keys;
+            }
+
+            self.mode = Mode::Normal;
+            document.end_group();
+            cursor.x = cursor.x.saturating_sub(1);
+            self.clamp(document, cursor);
+
+            return Outcome::Handled;
+        }
+
+        let motion_key = matches!(
+            key,
+            Key::Char(_)
+                | Key::Up
+                | Key::Down
+                | Key::Left
+                | Key::Right
+                | Key::Home
+                | Key::End
+                | Key::Backspace
+                | Key::Ctrl('r')
+        );
+
+        if key == Key::Esc {
+            self.pending.clear();
+
+            if self.mode == Mode::Visual {
+                self.mode = Mode::Normal;
+            }
+
+            return Outcome::Handled;
+        }
+
+        if self.pending.is_empty() && !motion_key {
+            return Outcome::Unhandled;
+        }
+
+        self.pending.push(key);
+
+        match parse(&self.pending, self.mode == Mode::Visual) {
+            Parse::Done((action, count)) => {
+                let keys = mem::take(&mut self.pending);
+
+                self.action(document, cursor, selection, action, count, keys)
+            }
+            Parse::Pending => Outcome::Handled,
+            Parse::Invalid => {
+                self.pending.clear();
+                Outcome::Handled
+            }
+        }
+    }
+
+    /// Keeps the cursor on a grapheme outside of insert mode.
+    pub fn clamp(&self, document: &Document, cursor: &mut Position) {
+        if self.mode != Mode::Insert {
+            let len = document.row(cursor.y).map_or(0, Row::len);
+
+            cursor.x = cursor.x.min(len.saturating_sub(1));
+        }
+    }
+
+    fn action(
+        &mut self,
+        document: &mut Document,
+        cursor: &mut Position,
+        selection: Option<(Position, Position)>,
+        action: Action,
+        count: Option<usize>,
+        keys: Vec<Key>,
+    ) -> Outcome {
+        let times = count.unwrap_or(1);
+        let change = match action {
+            Action::Operate(operator, _)
+            | Action::OperateLines(operator)
+            | Action::OperateSelection(operator) => operator != Operator::Yank,
+            Action::Insert(_) | Action::Paste { .. } => true,
+            _ => false,
+        };
+
+        if change {
+            document.begin_group();
+        }
+
+        match action {
+            Action::Move(motion) => {
+                if let Some(to) = target(document, cursor, motion, count) {
+                    document.seal_history();
+                    *cursor = to;
+                }
+            }
+            Action::Operate(operator, motion) if motion.linewise() => {
+                let from = cursor.y;
+
+                if let Some(to) = target(document, cursor, motion, count) {
+                    self.operate_lines(document, cursor, operator, from.min(to.y), from.max(to.y));
+                }
+            }
+            Action::Operate(operator, motion) => {
+                if let Some((start, end)) = range(document, cursor, operator, motion, count) {
+                    self.operate(document, cursor, operator, start, end);
+                }
+            }
+            Action::OperateLines(operator) => {
+                let y = cursor.y;
+
+                self.operate_lines(document, cursor, operator, y, y.saturating_add(times - 1));
+            }
+            Action::OperateSelection(operator) => {
+                let (start, mut end) = selection.unwrap_or((cursor.clone(), cursor.clone()));
+
+                // Visual selections include the grapheme under the cursor.
+                end.x += 1;
+                self.mode = Mode::Normal;
+                self.operate(document, cursor, operator, start, end);
+            }
+            Action::Insert(insert) => self.insert(document, cursor, insert),
+            Action::Visual => {
+                self.mode = match self.mode {
+                    Mode::Visual => Mode::Normal,
+                    _ => Mode::Visual,
+                };
+            }
+            Action::Paste { before } => self.paste(document, cursor, before, times),
+            Action::Undo => return Outcome::Undo(times),
+            Action::Redo => return Outcome::Redo(times),
+            Action::CommandLine => return Outcome::CommandLine,
+            Action::Repeat => {
+                self.replaying = true;
+
+                return Outcome::Replay(self.repeat_keys(count));
+            }
+        }
+
+        if change && !self.replaying && !matches!(action, Action::OperateSelection(_)) {
+            if self.mode == Mode::Insert {
+                self.recording = Some(keys);
+            } else {
+                self.last_change = keys;
+            }
+        }
+
+        if self.mode != Mode::Insert {
+            if change {
+                document.end_group();
+            }
+
+            self.clamp(document, cursor);
+        }
+
+        Outcome::Handled
+    }
+
+    /// Applies `operator` to the text from `start` to `end`.
+    pub fn operate(
+        &mut self,
+        document: &mut Document,
+        cursor: &mut Position,
+        operator: Operator,
+        start: Position,
+        end: Position,
+    ) {
+        self.register = Register {
+            text: document.text(&start, &end),
+            linewise: false,
+        };
+
+        if operator != Operator::Yank {
+            document.delete_range(&start, &end);
+        }
+
+        *cursor = start;
+
+        if operator == Operator::Change {
+            self.mode = Mode::Insert;
+        }
+    }
+
+    /// Applies `operator` to rows `first` to `last`, both included.
+    pub fn operate_lines(
+        &mut self,
+        document: &mut Document,
+        cursor: &mut Position,
+        operator: Operator,
+        first: usize,
+        last: usize,
+    ) {
+        if document.is_empty() {
+            return;
+        }
+
+        let row_len = |document: &Document, y: usize| document.row(y).map_or(0, Row::len);
+        let last = last.min(document.len() - 1);
+        let start = Position { x: 0, y: first };
+        let end = Position {
+            x: row_len(document, last),
+            y: last,
+        };
+        // Deleting the last rows also removes the line break before them.
+        let (delete_start, delete_end) = if last + 1 < document.len() {
+            (start.clone(), Position { x: 0, y: last + 1 })
+        } else if first > 0 {
+            let y = first - 1;
+
+            (
+                Position {
+                    x: row_len(document, y),
+                    y,
+                },
+                end.clone(),
+            )
+        } else {
+            (start.clone(), end.clone())
+        };
+
+        self.register = Register {
+            text: format!("{}\n", document.text(&start, &end)),
+            linewise: true,
+        };
+
+        match operator {
+            Operator::Yank => cursor.y = first,
+            Operator::Change => {
+                document.delete_range(&start, &end);
+                *cursor = start;
+                self.mode = Mode::Insert;
+            }
+            Operator::Delete => {
+                document.delete_range(&delete_start, &delete_end);
+
+                let y = first.min(document.len().saturating_sub(1));
+                let x = document.row(y).map_or(0, first_non_blank);
+
+                *cursor = Position { x, y };
+            }
+        }
+    }
+
+    /// Moves the cursor to where `insert` starts inserting, and switches to
+    /// insert mode.
+    pub fn insert(&mut self, document: &mut Document, cursor: &mut Position, insert: Insert) {
+        let Position { x, y } = *cursor;
+        let len = document.row(y).map_or(0, Row::len);
+
+        match insert {
+            Insert::Before => (),
+            Insert::After => cursor.x = (x + 1).min(len),
+            Insert::LineStart => cursor.x = document.row(y).map_or(0, first_non_blank),
+            Insert::LineEnd => cursor.x = len,
+            Insert::LineBelow => *cursor = document.insert_str(&Position { x: len, y }, "\n"),
+            Insert::LineAbove => {
+                document.insert_str(&Position { x: 0, y }, "\n");
+                *cursor = Position { x: 0, y };
+            }
+        }
+
+        self.mode = Mode::Insert;
+    }
+
+    /// Puts the register `times` after the cursor, or before it if `before`.
+    pub fn paste(
+        &self,
+        document: &mut Document,
+        cursor: &mut Position,
+        before: bool,
+        times: usize,
+    ) {
+        let text = self.register.text.repeat(times);
+        let Position { x, y } = *cursor;
+
+        if text.is_empty() {
+            return;
+        }
+
+        if !self.register.linewise {
+            let len = document.row(y).map_or(0, Row::len);
+            let at = Position {
+                x: if before { x } else { (x + 1).min(len) },
+                y,
+            };
+            let end = document.insert_str(&at, &text);
+
+            *cursor = Position {
+                x: end.x.saturating_sub(1),
+                y: end.y,
+            };
+            return;
+        }
+
+        let y = if before { y } else { y + 1 };
+
+        if y < document.len() {
+            document.insert_str(&Position { x: 0, y }, &text);
+        } else {
+            let last = document.len().saturating_sub(1);
+            let len = document.row(last).map_or(0, Row::len);
+            let lines = text.strip_suffix('\n').unwrap_or(&text);
+
+            document.insert_str(&Position { x: len, y: last }, &format!("\n{lines}"));
+        }
+
+        let x = document.row(y).map_or(0, first_non_blank);
+
+        *cursor = Position { x, y };
+    }
+
+    /// The keys of the last change, with `count` in place of the count they
+    /// were typed with.
+    pub fn repeat_keys(&self, count: Option<usize>) -> Vec<Key> {
+        let Some(count) = count else {
+            return self.last_change.clone();
+        };
+        let (_, keys) = split_count(&self.last_change);
+        let mut repeated = count.to_string().chars().map(Key::Char).collect::<Vec<_>>();
+
+        match keys.split_first() {
+            Some((&operator, rest)) if as_operator(operator).is_some() => {
+                repeated.push(operator);
+                repeated.extend_from_slice(split_count(rest).1);
+            }
+            _ => repeated.extend_from_slice(keys),
+        }
+
+        repeated
+    }
+}
+
+/// Reads keys typed in normal mode, or in visual mode if `visual`, such as
+/// `3dw` or `gg`, along with their count.
+pub fn parse(keys: &[Key], visual: bool) -> Parse<(Action, Option<usize>)> {
+    let (count, keys) = split_count(keys);
+    let Some((&key, rest)) = keys.split_first() else {
+        return Parse::Pending;
+    };
+
+    if let Some(operator) = as_operator(key) {
+        if visual {
+            return Parse::Done((Action::OperateSelection(operator), count));
+        }
+
+        let (motion_count, motion_keys) = split_count(rest);
+        let count = match (count, motion_count) {
+            (Some(count), Some(motion_count)) => Some(count.saturating_mul(motion_count)),
+            (count, motion_count) => count.or(motion_count),
+        };
+
+        if motion_keys.len() == 1 && as_operator(motion_keys[0]) == Some(operator) {
+            return Parse::Done((Action::OperateLines(operator), count));
+        }
+
+        return match motion(motion_keys) {
+            Parse::Done(motion) => Parse::Done((Action::Operate(operator, motion), count)),
+            Parse::Pending => Parse::Pending,
+            Parse::Invalid => Parse::Invalid,
+        };
+    }
+
+    let action = match key {
+        Key::Char('x') if visual => Some(Action::OperateSelection(Operator::Delete)),
+        Key::Char('v') => Some(Action::Visual),
+        Key::Char(':') => Some(Action::CommandLine),
+        Key::Char('u') => Some(Action::Undo),
+        Key::Ctrl('r') => Some(Action::Redo),
+        _ if visual => None,
+        Key::Char('x') => Some(Action::Operate(Operator::Delete, Motion::Right)),
+        Key::Char('X') => Some(Action::Operate(Operator::Delete, Motion::Left)),
+        Key::Char('D') => Some(Action::Operate(Operator::Delete, Motion::LineEnd)),
+        Key::Char('C') => Some(Action::Operate(Operator::Change, Motion::LineEnd)),
+        Key::Char('Y') => Some(Action::OperateLines(Operator::Yank)),
+        Key::Char('i') => Some(Action::Insert(Insert::Before)),
+        Key::Char('a') => Some(Action::Insert(Insert::After)),
+        Key::Char('I') => Some(Action::Insert(Insert::LineStart)),
+        Key::Char('A') => Some(Action::Insert(Insert::LineEnd)),
+        Key::Char('o') => Some(Action::Insert(Insert::LineBelow)),
+        Key::Char('O') => Some(Action::Insert(Insert::LineAbove)),
+        Key::Char('p') => Some(Action::Paste { before: false }),
+        Key::Char('P') => Some(Action::Paste { before: true }),
+        Key::Char('.') => Some(Action::Repeat),
+        _ => None,
+    };
+
+    match action {
+        Some(action) if rest.is_empty() => Parse::Done((action, count)),
+        Some(_) => Parse::Invalid,
+        None => match motion(keys) {
+            Parse::Done(motion) => Parse::Done((Action::Move(motion), count)),
+            Parse::Pending => Parse::Pending,
+            Parse::Invalid => Parse::Invalid,
+        },
+    }
+}
+
+fn split_count(keys: &[Key]) -> (Option<usize>, &[Key]) {
+    let digits = keys
+        .iter()
+        .enumerate()
+        .take_while(|(index, key)| match key {
+            Key::Char('1'..='9') => true,
+            Key::Char('0') => *index > 0,
+            _ => false,
+        })
+        .count();
+    let count = keys[..digits]
+        .iter()
+        .filter_map(|key| match key {
+            Key::Char(c) => c.to_digit(10),
+            _ => None,
+        })
+        .try_fold(0_usize, |count, digit| {
+            count.checked_mul(10)?.checked_add(digit as usize)
+        });
+
+    match count {
+        Some(count) if digits > 0 => (Some(count), &keys[digits..]),
+        _ => (None, &keys[digits..]),
+    }
+}
+
+fn as_operator(key: Key) -> Option<Operator> {
+    match key {
+        Key::Char('d') => Some(Operator::Delete),
+        Key::Char('c') => Some(Operator::Change),
+        Key::Char('y') => Some(Operator::Yank),
+        _ => None,
+    }
+}
+
+fn motion(keys: &[Key]) -> Parse<Motion> {
+    let motion = match keys {
+        [] | [Key::Char('g' | 'f' | 't' | 'F' | 'T')] => return Parse::Pending,
+        [Key::Char('h') | Key::Left | Key::Backspace] => Motion::Left,
+        [Key::Char('l' | ' ') | Key::Right] => Motion::Right,
+        [Key::Char('k') | Key::Up] => Motion::Up,
+        [Key::Char('j' | '\n') | Key::Down] => Motion::Down,
+        [Key::Char('w')] => Motion::WordForward,
+        [Key::Char('b')] => Motion::WordBackward,
+        [Key::Char('e')] => Motion::WordEnd,
+        [Key::Char('0') | Key::Home] => Motion::LineStart,
+        [Key::Char('$') | Key::End] => Motion::LineEnd,
+        [Key::Char('G')] => Motion::LastLine,
+        [Key::Char('g'), Key::Char('g')] => Motion::FirstLine,
+        [Key::Char('f'), Key::Char(c)] => Motion::Find(*c),
+        [Key::Char('t'), Key::Char(c)] => Motion::Till(*c),
+        [Key::Char('F'), Key::Char(c)] => Motion::FindBackward(*c),
+        [Key::Char('T'), Key::Char(c)] => Motion::TillBackward(*c),
+        _ => return Parse::Invalid,
+    };
+
+    Parse::Done(motion)
+}
+
+/// Where `motion` repeated `count` times goes from `from`, or `None` if a
+/// character to find is not on the line. The column may be the end of the
+/// row, for operators to cover its last grapheme.
+pub fn target(
+    document: &Document,
+    from: &Position,
+    motion: Motion,
+    count: Option<usize>,
+) -> Option<Position> {
+    let times = count.unwrap_or(1);
+    let last_row = document.len().saturating_sub(1);
+    let row_len = |y: usize| document.row(y).map_or(0, Row::len);
+    let on_row = |y: usize| Position {
+        x: from.x.min(row_len(y)),
+        y,
+    };
+    let target = match motion {
+        Motion::Left => Position {
+            x: from.x.saturating_sub(times),
+            y: from.y,
+        },
+        Motion::Right => Position {
+            x: from.x.saturating_add(times).min(row_len(from.y)),
+            y: from.y,
+        },
+        Motion::Up => on_row(from.y.saturating_sub(times)),
+        Motion::Down => on_row(from.y.saturating_add(times).min(last_row)),
+        Motion::LineStart => Position { x: 0, y: from.y },
+        Motion::LineEnd => {
+            let y = from.y.saturating_add(times - 1).min(last_row);
+
+            Position { x: row_len(y), y }
+        }
+        Motion::FirstLine | Motion::LastLine => {
+            let y = match count {
+                Some(line) => line.saturating_sub(1).min(last_row),
+                None if motion == Motion::FirstLine => 0,
+                None => last_row,
+            };
+
+            Position {
+                x: document.row(y).map_or(0, first_non_blank),
+                y,
+            }
+        }
+        Motion::WordForward | Motion::WordBackward | Motion::WordEnd => {
+            let mut at = from.clone();
+
+            for _ in 0..times {
+                let next = match motion {
+                    Motion::WordForward => word_forward(document, at.clone()),
+                    Motion::WordBackward => word_backward(document, at.clone()),
+                    _ => word_end(document, at.clone()),
+                };
+
+                if next == at {
+                    break;
+                }
+
+                at = next;
+            }
+
+            at
+        }
+        Motion::Find(c) | Motion::Till(c) | Motion::FindBackward(c) | Motion::TillBackward(c) => {
+            let row = document.row(from.y)?;
+            let matches = row
+                .as_string()
+                .graphemes(true)
+                .enumerate()
+                .filter(|(_, grapheme)| grapheme.starts_with(c))
+                .map(|(x, _)| x)
+                .collect::<Vec<_>>();
+            let mut after = matches.iter().filter(|x| **x > from.x);
+            let mut before = matches.iter().rev().filter(|x| **x < from.x);
+            let x = match motion {
+                Motion::Find(_) => *after.nth(times - 1)?,
+                Motion::Till(_) => after.filter(|x| **x > from.x + 1).nth(times - 1)? - 1,
+                Motion::FindBackward(_) => *before.nth(times - 1)?,
+                _ => before.filter(|x| *x + 1 < from.x).nth(times - 1)? + 1,
+            };
+
+            Position { x, y: from.y }
+        }
+    };
+
+    Some(target)
+}
+
+/// The text `operator` with `motion` covers from `from`, end excluded, or
+/// `None` if the motion fails. Linewise motions are left to the caller.
+pub fn range(
+    document: &Document,
+    from: &Position,
+    operator: Operator,
+    motion: Motion,
+    count: Option<usize>,
+) -> Option<(Position, Position)> {
+    let mut to = match class(document, from) {
+        // `cw` on a word changes up to its end, like `ce`.
+        Class::Word | Class::Punctuation
+            if operator == Operator::Change && motion == Motion::WordForward =>
+        {
+            let word = class(document, from);
+            let mut at = from.clone();
+
+            while let Some(next_at) = document
+                .next_position(&at)
+                .filter(|next| class(document, next) == word)
+            {
+                at = next_at;
+            }
+
+            for _ in 1..count.unwrap_or(1) {
+                at = word_end(document, at);
+            }
+
+            Position {
+                x: at.x + 1,
+                y: at.y,
+            }
+        }
+        _ => target(document, from, motion, count)?,
+    };
+
+    // An operator with `w` stops at the end of the line of the last word.
+    if motion == Motion::WordForward && to.y > from.y && operator != Operator::Change {
+        let y = to.y - 1;
+
+        to = Position {
+            x: document.row(y).map_or(0, Row::len),
+            y,
+        };
+    }
+
+    if motion.inclusive() {
+        to.x += 1;
+    }
+
+    if (to.y, to.x) < (from.y, from.x) {
+        return Some((to, from.clone()));
+    }
+
+    Some((from.clone(), to))
+}
+
+/// Index of the first grapheme of `row` that is not a space or a tab.
+pub fn first_non_blank(row: &Row) -> usize {
+    row.as_string()
+        .graphemes(true)
+        .take_while(|grapheme| grapheme.chars().all(char::is_whitespace))
+        .count()
+}
+
+/// The grapheme at `at`, where the end of a row counts as a blank.
+fn class(document: &Document, at: &Position) -> Class {
+    let Some(row) = document.row(at.y) else {
+        return Class::Blank;
+    };
+
+    if row.is_empty() {
+        return Class::EmptyLine;
+    }
+
+    let grapheme = row.as_string()[row.byte_index(at.x)..]
+        .graphemes(true)
+        .next();
+
+    match grapheme.and_then(|grapheme| grapheme.chars().next()) {
+        Some(c) if c.is_alphanumeric() || c == '_' => Class::Word,
+        Some(c) if !c.is_whitespace() => Class::Punctuation,
+        _ => Class::Blank,
+    }
+}
+
+fn word_forward(document: &Document, mut at: Position) -> Position {
+    let start = class(document, &at);
+
+    while class(document, &at) == start && start != Class::Blank {
+        match document.next_position(&at) {
+            Some(next) => at = next,
+            None => return at,
+        }
+
+        if start == Class::EmptyLine {
+            break;
+        }
+    }
+
+    while class(document, &at) == Class::Blank {
+        match document.next_position(&at) {
+            Some(next) => at = next,
+            None => return at,
+        }
+    }
+
+    at
+}
+
+fn word_end(document: &Document, mut at: Position) -> Position {
+    let Some(mut next_at) = document.next_position(&at) else {
+        return at;
+    };
+
+    while matches!(class(document, &next_at), Class::Blank | Class::EmptyLine) {
+        match document.next_position(&next_at) {
+            Some(next) => next_at = next,
+            None => return at,
+        }
+    }
+
+    let word = class(document, &next_at);
+
+    at = next_at;
+
+    while let Some(next_at) = document
+        .next_position(&at)
+        .filter(|next| class(document, next) == word)
+    {
+        at = next_at;
+    }
+
+    at
+}
+
+fn word_backward(document: &Document, mut at: Position) -> Position {
+    let Some(mut previous_at) = document.previous_position(&at) else {
+        return at;
+    };
+
+    while class(document, &previous_at) == Class::Blank {
+        match document.previous_position(&previous_at) {
+            Some(previous) => previous_at = previous,
+            None => return previous_at,
+        }
+    }
+
+    let word = class(document, &previous_at);
+
+    at = previous_at;
+
+    if word == Class::EmptyLine {
+        return at;
+    }
+
+    while let Some(previous_at) = document
+        .previous_position(&at)
+        .filter(|previous| class(document, previous) == word)
+    {
+        at = previous_at;
+    }
+
+    at
+}
+
+#[cfg(test)]
+mod vi_tests {
+    use super::*;
+
+    fn keys(text: &str) -> Vec<Key> {
+        text.chars().map(Key::Char).collect()
+    }
+
+    fn document(text: &str) -> Document {
+        let mut document = Document::default();
+
+        document.insert_str(&Position::default(), text);
+        document
+    }
+
+    fn position(x: usize, y: usize) -> Position {
+        Position { x, y }
+    }
+
+    fn contents(document: &Document) -> String {
+        let last = document.len().saturating_sub(1);
+        let end = position(document.row(last).map_or(0, Row::len), last);
+
+        document.text(&Position::default(), &end)
+    }
+
+    /// Presses `keys`, typing the characters vi leaves to the editor.
+    fn press(vi: &mut Vi, document: &mut Document, cursor: &mut Position, keys: &[Key]) {
+        for key in keys {
+            match vi.key(document, cursor, None, *key) {
+                Outcome::Unhandled => {
+                    if let Key::Char(c) = key {
+                        *cursor = document.insert_str(cursor, &c.to_string());
+                    }
+                }
+                Outcome::Replay(keys) => {
+                    press(vi, document, cursor, &keys);
+                    vi.replaying = false;
+                }
+                _ => (),
+            }
+        }
+    }
+
+    #[test]
+    fn test_parse() {
+        let parse = |text: &str| parse(&keys(text), false);
+
+        assert_eq!(
+            parse("3dw"),
+            Parse::Done((
+                Action::Operate(Operator::Delete, Motion::WordForward),
+                Some(3)
+            ))
+        );
+        assert_eq!(
+            parse("2d3w"),
+            Parse::Done((
+                Action::Operate(Operator::Delete, Motion::WordForward),
+                Some(6)
+            ))
+        );
+        assert_eq!(
+            parse("cc"),
+            Parse::Done((Action::OperateLines(Operator::Change), None))
+        );
+        assert_eq!(
+            parse("ytx"),
+            Parse::Done((Action::Operate(Operator::Yank, Motion::Till('x')), None))
+        );
+        assert_eq!(
+            parse("10G"),
+            Parse::Done((Action::Move(Motion::LastLine), Some(10)))
+        );
+        assert_eq!(
+            parse("0"),
+            Parse::Done((Action::Move(Motion::LineStart), None))
+        );
+        assert_eq!(parse("g"), Parse::Pending);
+        assert_eq!(parse("d2"), Parse::Pending);
+        assert_eq!(parse("df"), Parse::Pending);
+        assert_eq!(parse("gx"), Parse::Invalid);
+        assert_eq!(parse("dy"), Parse::Invalid);
+        assert_eq!(
+            super::parse(&keys("d"), true),
+            Parse::Done((Action::OperateSelection(Operator::Delete), None))
+        );
+        assert_eq!(super::parse(&keys("i"), true), Parse::Invalid);
+    }
+
+    #[test]
+    fn test_word_motions() {
+        let document = document("let x = foo(bar);\n\n  baz");
+        let go = |x, y, motion, count| target(&document, &position(x, y), motion, count).unwrap();
+
+        assert_eq!(go(0, 0, Motion::WordForward, None), position(4, 0));
+        assert_eq!(go(8, 0, Motion::WordForward, None), position(11, 0));
+        assert_eq!(go(8, 0, Motion::WordForward, Some(3)), position(15, 0));
+        assert_eq!(go(16, 0, Motion::WordForward, None), position(0, 1));
+        assert_eq!(go(0, 1, Motion::WordForward, None), position(2, 2));
+        assert_eq!(go(0, 0, Motion::WordEnd, None), position(2, 0));
+        assert_eq!(go(2, 0, Motion::WordEnd, None), position(4, 0));
+        assert_eq!(go(16, 0, Motion::WordEnd, None), position(4, 2));
+        assert_eq!(go(2, 2, Motion::WordBackward, None), position(0, 1));
+        assert_eq!(go(0, 1, Motion::WordBackward, None), position(15, 0));
+        assert_eq!(go(12, 0, Motion::WordBackward, Some(2)), position(8, 0));
+        assert_eq!(go(0, 0, Motion::WordBackward, None), position(0, 0));
+    }
+
+    #[test]
+    fn test_line_motions() {
+        let document = document("one\n  two\nthree");
+        let go = |x, y, motion, count| target(&document, &position(x, y), motion, count);
+
+        assert_eq!(go(4, 2, Motion::Up, None), Some(position(4, 1)));
+        assert_eq!(go(4, 2, Motion::Up, Some(2)), Some(position(3, 0)));
+        assert_eq!(go(1, 0, Motion::Down, Some(9)), Some(position(1, 2)));
+        assert_eq!(go(1, 0, Motion::LineEnd, None), Some(position(3, 0)));
+        assert_eq!(go(1, 0, Motion::LineEnd, Some(2)), Some(position(5, 1)));
+        assert_eq!(go(3, 2, Motion::FirstLine, None), Some(position(0, 0)));
+        assert_eq!(go(0, 0, Motion::LastLine, Some(2)), Some(position(2, 1)));
+        assert_eq!(go(0, 0, Motion::LastLine, None), Some(position(0, 2)));
+        assert_eq!(go(0, 2, Motion::Find('e'), Some(2)), Some(position(4, 2)));
+        assert_eq!(go(0, 2, Motion::Till('e'), None), Some(position(2, 2)));
+        assert_eq!(
+            go(4, 2, Motion::FindBackward('h'), None),
+            Some(position(1, 2))
+        );
+        assert_eq!(
+            go(4, 2, Motion::TillBackward('h'), None),
+            Some(position(2, 2))
+        );
+        assert_eq!(go(0, 2, Motion::Find('z'), None), None);
+    }
+
+    #[test]
+    fn test_operator_ranges() {
+        let document = document("foo bar.baz\nqux");
+        let range = |x, operator, motion, count| {
+            range(&document, &position(x, 0), operator, motion, count).unwrap()
+        };
+
+        assert_eq!(
+            range(0, Operator::Delete, Motion::WordForward, None),
+            (position(0, 0), position(4, 0))
+        );
+        assert_eq!(
+            range(0, Operator::Change, Motion::WordForward, None),
+            (position(0, 0), position(3, 0))
+        );
+        assert_eq!(
+            range(4, Operator::Change, Motion::WordForward, Some(2)),
+            (position(4, 0), position(8, 0))
+        );
+        assert_eq!(
+            range(8, Operator::Delete, Motion::WordForward, None),
+            (position(8, 0), position(11, 0))
+        );
+        assert_eq!(
+            range(4, Operator::Delete, Motion::WordEnd, None),
+            (position(4, 0), position(7, 0))
+        );
+        assert_eq!(
+            range(4, Operator::Yank, Motion::Till('z'), None),
+            (position(4, 0), position(10, 0))
+        );
+        assert_eq!(
+            range(4, Operator::Delete, Motion::FindBackward('o'), None),
+            (position(2, 0), position(4, 0))
+        );
+    }
+
+    #[test]
+    fn test_operate_lines() {
+        let mut vi = Vi::default();
+        let mut document = document("one\n  two\nthree\nfour");
+        let mut cursor = position(2, 0);
+
+        press(&mut vi, &mut document, &mut cursor, &keys("3dd"));
+        assert_eq!(contents(&document), "four");
+        assert_eq!(vi.register.text, "one\n  two\nthree\n");
+        assert!(vi.register.linewise);
+        assert_eq!(cursor, position(0, 0));
+
+        let mut document = self::document("one\n  two\nthree\nfour");
+        let mut cursor = position(0, 2);
+
+        press(&mut vi, &mut document, &mut cursor, &keys("3dd"));
+        assert_eq!(contents(&document), "one\n  two");
+        assert_eq!(vi.register.text, "three\nfour\n");
+        assert_eq!(cursor, position(2, 1));
+    }
+
+    #[test]
+    fn test_paste() {
+        let mut vi = Vi::default();
+        let mut document = document("one\ntwo");
+        let mut cursor = position(1, 1);
+
+        press(&mut vi, &mut document, &mut cursor, &keys("yyp"));
+        assert_eq!(contents(&document), "one\ntwo\ntwo");
+        assert_eq!(cursor, position(0, 2));
+
+        press(&mut vi, &mut document, &mut cursor, &keys("kP"));
+        assert_eq!(contents(&document), "one\ntwo\ntwo\ntwo");
+        assert_eq!(cursor, position(0, 1));
+
+        press(&mut vi, &mut document, &mut cursor, &keys("gg$yhp"));
+        assert_eq!(contents(&document), "onne\ntwo\ntwo\ntwo");
+        assert_eq!(cursor, position(2, 0));
+    }
+
+    #[test]
+    fn test_repeat() {
+        let mut vi = Vi::default();
+        let mut document = document("foo bar baz");
+        let mut cursor = position(0, 0);
+        let change = [keys("cwnew"), vec![Key::Esc]].concat();
+
+        press(&mut vi, &mut document, &mut cursor, &change);
+        assert_eq!(contents(&document), "new bar baz");
+        assert_eq!(cursor, position(2, 0));
+
+        press(&mut vi, &mut document, &mut cursor, &keys("w."));
+        assert_eq!(contents(&document), "new new baz");
+        assert_eq!(cursor, position(6, 0));
+        assert_eq!(vi.last_change, change);
+
+        let mut document = self::document("a b c d e f g");
+        let mut cursor = position(0, 0);
+
+        press(&mut vi, &mut document, &mut cursor, &keys("3dw"));
+        assert_eq!(contents(&document), "d e f g");
+
+        press(&mut vi, &mut document, &mut cursor, &keys("2."));
+        assert_eq!(contents(&document), "f g");
+        assert_eq!(vi.last_change, keys("3dw"));
+
+        press(&mut vi, &mut document, &mut cursor, &keys("A"));
+        press(
+            &mut vi,
+            &mut document,
+            &mut cursor,
+            &[Key::Ctrl('s'), Key::Char('!'), Key::Esc],
+        );
+        assert_eq!(vi.last_change, [Key::Char('A'), Key::Char('!'), Key::Esc]);
+
+        vi.last_change = keys("2d3w");
+        assert_eq!(vi.repeat_keys(Some(4)), keys("4dw"));
+        assert_eq!(vi.repeat_keys(None), keys("2d3w"));
+    }
+}